@@ -0,0 +1,234 @@
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::read::GzDecoder;
+use tar::EntryType;
+
+use crate::config;
+
+
+// decode the base64 archive of a submission
+// the decoded archive must not be larger than the limit of the server
+pub fn decode(archive: &str, server: &config::Server) -> io::Result<Vec<u8>> {
+    let data = STANDARD.decode(archive.trim())
+        .map_err(|err| { invalid(format!("invalid base64 {}", err)) })?;
+    if data.len() > server.archive_size_limit {
+        return Err(invalid(format!("the archive is larger than {} bytes", server.archive_size_limit)));
+    }
+    return Ok(data);
+}
+
+// extract the base64 archive of a submission into the directory
+// zip, tar and gzipped tar archives are told apart by their magic numbers
+// only the regular files and directories are extracted
+// links and paths leading out of the directory are rejected
+// the number of entries and the total size of the files are limited
+// any problem of the archive itself is an InvalidData error
+pub fn extract(archive: &str, dir: &Path, server: &config::Server) -> io::Result<()> {
+    let data = decode(archive, server)?;
+    let mut extractor = Extractor { dir, server, entries: 0, size: 0 };
+    if data.starts_with(b"PK\x03\x04") {
+        return extract_zip(data, &mut extractor);
+    }
+    if data.starts_with(&[0x1f, 0x8b]) {
+        return extract_tar(GzDecoder::new(Cursor::new(data)), &mut extractor);
+    }
+    return extract_tar(Cursor::new(data), &mut extractor);
+}
+
+fn extract_tar<R: Read>(reader: R, extractor: &mut Extractor) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let path = entry.path().map_err(invalid)?.into_owned();
+        match entry.header().entry_type() {
+            EntryType::Directory => extractor.create_dir(&path)?,
+            EntryType::Regular | EntryType::Continuous => {
+                let mode = entry.header().mode().unwrap_or(0o644);
+                extractor.write_file(&path, &mut entry, mode)?;
+            },
+            // the global pax header only has metadata
+            EntryType::XGlobalHeader => {},
+            _ => return Err(invalid(format!("{} is not a regular file or directory", path.display()))),
+        }
+    }
+    return Ok(());
+}
+
+fn extract_zip(data: Vec<u8>, extractor: &mut Extractor) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(invalid)?;
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx).map_err(invalid)?;
+        let path = PathBuf::from(file.name());
+        let mode = file.unix_mode().unwrap_or(0o644);
+        if file.is_dir() {
+            extractor.create_dir(&path)?;
+        } else if mode & libc::S_IFMT != 0 && mode & libc::S_IFMT != libc::S_IFREG {
+            return Err(invalid(format!("{} is not a regular file or directory", path.display())));
+        } else {
+            extractor.write_file(&path, &mut file, mode)?;
+        }
+    }
+    return Ok(());
+}
+
+// write the entries of an archive into the directory within the limits
+struct Extractor<'a> {
+    dir: &'a Path,
+    server: &'a config::Server,
+    entries: usize,
+    size: u64
+}
+
+impl Extractor<'_> {
+    // the path of an entry in the directory
+    // only the normal components are allowed, so the path stays in the directory
+    fn target(&mut self, name: &Path) -> io::Result<PathBuf> {
+        self.entries += 1;
+        if self.entries > self.server.archive_file_limit {
+            return Err(invalid(format!("the archive has more than {} entries", self.server.archive_file_limit)));
+        }
+        let mut path = self.dir.to_path_buf();
+        for component in name.components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => {},
+                _ => return Err(invalid(format!("invalid path {}", name.display()))),
+            }
+        }
+        if path == self.dir {
+            return Err(invalid(format!("invalid path {}", name.display())));
+        }
+        return Ok(path);
+    }
+    fn create_dir(&mut self, name: &Path) -> io::Result<()> {
+        let path = self.target(name)?;
+        return fs::create_dir_all(path).map_err(|err| { conflict(name, err) });
+    }
+    // the content is read before writing so that a broken archive is told apart from a failed write
+    fn write_file(&mut self, name: &Path, reader: &mut dyn Read, mode: u32) -> io::Result<()> {
+        let path = self.target(name)?;
+        let limit = self.server.archive_unpacked_limit.saturating_sub(self.size);
+        let mut content = Vec::new();
+        reader.take(limit + 1).read_to_end(&mut content).map_err(invalid)?;
+        if content.len() as u64 > limit {
+            return Err(invalid(format!("the files are larger than {} bytes", self.server.archive_unpacked_limit)));
+        }
+        self.size += content.len() as u64;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| { conflict(name, err) })?;
+        }
+        fs::write(&path, &content).map_err(|err| { conflict(name, err) })?;
+        // only keep whether the file is executable, such as a script
+        let mode = if mode & 0o111 != 0 { 0o755 } else { 0o644 };
+        return fs::set_permissions(&path, fs::Permissions::from_mode(mode));
+    }
+}
+
+fn invalid<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// a file and a directory of the same path in the archive
+fn conflict(name: &Path, err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::EEXIST) | Some(libc::ENOTDIR) | Some(libc::EISDIR) => {
+            invalid(format!("{} conflicts with another entry", name.display()))
+        },
+        _ => err,
+    }
+}
+
+// some simple tests
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+    use crate::config::Config;
+
+    fn server() -> config::Server {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        config.server
+    }
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            // set the name without the checks of the builder
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append(&header, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+    fn extract_into(dir: &str, data: &[u8], server: &config::Server) -> io::Result<()> {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        extract(&STANDARD.encode(data), Path::new(dir), server)
+    }
+
+    #[test]
+    fn test_extract() {
+        let server = server();
+        let dir = "./tmp/archive_test";
+        let data = tar(&[("main.sh", b"echo hi"), ("lib/add.sh", b"expr $1 + $2")]);
+        extract_into(dir, &data, &server).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/lib/add.sh", dir)).unwrap(), "expr $1 + $2");
+        let mode = fs::metadata(format!("{}/main.sh", dir)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+
+        // gzipped tar
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        extract_into(dir, &encoder.finish().unwrap(), &server).unwrap();
+        assert!(Path::new(&format!("{}/lib/add.sh", dir)).is_file());
+
+        // zip
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.add_directory("src", Default::default()).unwrap();
+        writer.start_file("src/main.rs", Default::default()).unwrap();
+        writer.write_all(b"fn main() {}").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        extract_into(dir, &data, &server).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/src/main.rs", dir)).unwrap(), "fn main() {}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_reject() {
+        let mut server = server();
+        let dir = "./tmp/archive_reject_test";
+        let kind = |res: io::Result<()>| { res.unwrap_err().kind() };
+        assert_eq!(kind(extract_into(dir, &tar(&[("../evil", b"")]), &server)), io::ErrorKind::InvalidData);
+        assert_eq!(kind(extract_into(dir, &tar(&[("/tmp/evil", b"")]), &server)), io::ErrorKind::InvalidData);
+        assert!(!Path::new("./tmp/evil").exists());
+        assert_eq!(kind(extract_into(dir, &tar(&[("a", b""), ("a/b", b"")]), &server)), io::ErrorKind::InvalidData);
+        assert_eq!(kind(extract("not base64!", Path::new(dir), &server)), io::ErrorKind::InvalidData);
+
+        // links are not extracted
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "passwd", "/etc/passwd").unwrap();
+        let data = builder.into_inner().unwrap();
+        assert_eq!(kind(extract_into(dir, &data, &server)), io::ErrorKind::InvalidData);
+        assert!(!Path::new(&format!("{}/passwd", dir)).exists());
+
+        // the limits
+        server.archive_file_limit = 1;
+        assert_eq!(kind(extract_into(dir, &tar(&[("a", b""), ("b", b"")]), &server)), io::ErrorKind::InvalidData);
+        server.archive_unpacked_limit = 4;
+        assert_eq!(kind(extract_into(dir, &tar(&[("a", b"12345")]), &server)), io::ErrorKind::InvalidData);
+        server.archive_size_limit = 16;
+        assert_eq!(kind(extract_into(dir, &tar(&[("a", b"")]), &server)), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -10,50 +10,284 @@ use serde_json::value::Value;
 // language struct represent the language set
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
-    server: Server,
+    pub server: Server,
+    #[serde(default)]
+    pub sandbox: Sandbox,
     pub problems: Vec<Problem>,
     pub languages: Vec<Language>
 }
 
 #[derive(Debug,Serialize, Deserialize, Clone)]
-struct Server {
+pub struct Server {
     #[serde(default = "default_address")]
-    bind_address: String,
+    pub bind_address: String,
     #[serde(default = "default_port")]
-    bind_port: u16
+    pub bind_port: u16,
+    // the number of the http worker threads
+    // use the number of cpus if not set
+    #[serde(default)]
+    pub workers: Option<usize>,
+    // the max size in bytes of a json request body
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    // the keep-alive timeout in seconds, 0 to disable keep-alive
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: u64,
+    // the number of the judge workers running jobs in background
+    #[serde(default = "default_judge_workers")]
+    pub judge_workers: usize,
+    // the file to store the jobs, users and contests
+    #[serde(default = "default_data_file")]
+    pub data_file: String,
+    // the max size in bytes of the compiler output kept for a job
+    #[serde(default = "default_compile_output_limit")]
+    pub compile_output_limit: usize,
+    // the max size in bytes of the stderr kept for each case
+    #[serde(default = "default_stderr_limit")]
+    pub stderr_limit: usize,
+    // show the beginning of the stderr in the case info to the users
+    // otherwise it is only kept in the job directory
+    #[serde(default)]
+    pub show_stderr: bool,
+    // the max size in bytes of an uploaded archive after the base64 decoding
+    #[serde(default = "default_archive_size_limit")]
+    pub archive_size_limit: usize,
+    // the max number of the entries in an uploaded archive
+    #[serde(default = "default_archive_file_limit")]
+    pub archive_file_limit: usize,
+    // the max total size in bytes of the files extracted from an uploaded archive
+    #[serde(default = "default_archive_unpacked_limit")]
+    pub archive_unpacked_limit: u64
 }
 
 fn default_address() -> String { "127.0.0.1".to_string() }
 
 fn default_port() -> u16 { 12345 }
 
+fn default_max_body_size() -> usize { 2 * 1024 * 1024 }
+
+fn default_keep_alive() -> u64 { 5 }
+
+fn default_judge_workers() -> usize { 4 }
+
+fn default_data_file() -> String { "./data/oj.json".to_string() }
+
+fn default_compile_output_limit() -> usize { 16 * 1024 }
+
+fn default_stderr_limit() -> usize { 64 * 1024 }
+
+fn default_archive_size_limit() -> usize { 1024 * 1024 }
+
+fn default_archive_file_limit() -> usize { 256 }
+
+fn default_archive_unpacked_limit() -> u64 { 16 * 1024 * 1024 }
+
+// the sandbox of the judged programs, enabled unless it is turned off explicitly
+// such as where the user namespaces are not available
+// hidden_paths are the other files and directories the programs can not read
+// the config file, the data file and the case files are always hidden
+// max_processes limits the processes and threads of a program, such as a shell script, 0 for no limit
+// the limit does not apply when the server runs as root
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Sandbox {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub hidden_paths: Vec<String>,
+    #[serde(default = "default_max_processes")]
+    pub max_processes: u64
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hidden_paths: Vec::new(),
+            max_processes: default_max_processes()
+        }
+    }
+}
+
+fn default_enabled() -> bool { true }
+
+fn default_max_processes() -> u64 { 64 }
+
+// the packing of a problem is checked when the config is parsed
 #[derive(Debug,Serialize, Deserialize, Clone)]
+#[serde(try_from = "ProblemData")]
 pub struct Problem {
     pub id: u32,
     pub name: String,
-    #[serde(rename(deserialize = "type"))]
-    pub problem_type: String,
+    pub problem_type: ProblemType,
     pub misc: Value,
     pub cases: Vec<Case>
 }
 
+// the problem in the config file before it is checked
+#[derive(Deserialize)]
+struct ProblemData {
+    id: u32,
+    name: String,
+    #[serde(rename = "type")]
+    problem_type: ProblemType,
+    misc: Value,
+    cases: Vec<Case>
+}
+
+impl TryFrom<ProblemData> for Problem {
+    type Error = String;
+    fn try_from(data: ProblemData) -> Result<Self, Self::Error> {
+        let problem = Problem {
+            id: data.id,
+            name: data.name,
+            problem_type: data.problem_type,
+            misc: data.misc,
+            cases: data.cases
+        };
+        if let Err(err) = problem.groups() {
+            return Err(format!("invalid packing of problem {}: {}", problem.id, err));
+        }
+        Ok(problem)
+    }
+}
+
+// the type of the problem decides how the output is compared
+// standard ignores the trailing whitespace of each line and the trailing blank lines
+// strict compares the output byte by byte
+// spj uses the special judge in misc
+// dynamic_ranking compares like standard and scores with the running time
+// interactive runs the program together with the interactor in misc
+// an unknown type fails the parsing of the config
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ProblemType {
+    standard,
+    strict,
+    spj,
+    dynamic_ranking,
+    interactive
+}
+
+impl Problem {
+    // the share of the score decided by the running time
+    // only dynamic ranking problems have the share
+    pub fn dynamic_ranking_ratio(&self) -> Option<f32> {
+        if self.problem_type != ProblemType::dynamic_ranking {
+            return None;
+        }
+        self.misc.get("dynamic_ranking_ratio")
+            .and_then(|ratio| ratio.as_f64())
+            .map(|ratio| ratio as f32)
+    }
+    // the case groups of packed judging from misc.packing
+    // each group is a list of case indices starting from 1
+    // a case not in any group is judged as a group of its own
+    pub fn packing(&self) -> Vec<Vec<usize>> {
+        let mut groups = self.groups().unwrap_or_default();
+        for idx in 1..=self.cases.len() {
+            if !groups.iter().any(|group| { group.contains(&idx) }) {
+                groups.push(vec![idx]);
+            }
+        }
+        groups
+    }
+    // the groups in misc.packing
+    // an index out of the cases or in more than one group is an error
+    fn groups(&self) -> Result<Vec<Vec<usize>>, String> {
+        let groups: Vec<Vec<usize>> = match self.misc.get("packing") {
+            Some(packing) => serde_json::from_value(packing.clone()).map_err(|err| { err.to_string() })?,
+            None => return Ok(Vec::new()),
+        };
+        let mut seen = vec![false; self.cases.len() + 1];
+        for idx in groups.iter().flatten().copied() {
+            if idx < 1 || idx > self.cases.len() {
+                return Err(format!("case {} does not exist", idx));
+            }
+            if seen[idx] {
+                return Err(format!("case {} is in more than one group", idx));
+            }
+            seen[idx] = true;
+        }
+        Ok(groups)
+    }
+    // the grader files of the language from misc.graders, such as a main and its header
+    // misc.graders maps the name of a language to a list of files
+    pub fn graders(&self, language: &str) -> Vec<String> {
+        self.misc.get("graders")
+            .and_then(|graders| graders.get(language))
+            .and_then(|files| serde_json::from_value(files.clone()).ok())
+            .unwrap_or_default()
+    }
+    // the file the program reads from its work dir instead of the stdin
+    // from misc.input_file, such as "input.txt"
+    pub fn input_file_name(&self) -> Option<&str> {
+        self.misc.get("input_file").and_then(|name| name.as_str())
+    }
+    // the file the program writes in its work dir instead of the stdout
+    // from misc.output_file, such as "output.txt"
+    pub fn output_file_name(&self) -> Option<&str> {
+        self.misc.get("output_file").and_then(|name| name.as_str())
+    }
+}
+
 #[derive(Debug,Serialize, Deserialize, Clone)]
 pub struct Case {
     pub score: f32,
     pub input_file: String,
     pub answer_file: String,
+    // the cpu time limit in microseconds
     pub time_limit: u32,
-    pub memory_limit: u32
+    pub memory_limit: u32,
+    // the wall clock time limit in microseconds
+    // 0 for one second more than the cpu time limit
+    #[serde(default)]
+    pub wall_time_limit: u32,
+    // the max size in bytes of the output, 0 for no limit
+    #[serde(default = "default_output_limit")]
+    pub output_limit: u64
 }
 
+fn default_output_limit() -> u64 { 64 * 1024 * 1024 }
+
 
 #[derive(Debug,Serialize, Deserialize, Clone)]
 pub struct Language {
     pub name: String,
     pub file_name: String,
-    pub command: Vec<String>
+    // the compile command, which can also be written as "compile"
+    // leave it empty for an interpreted language without a compile step
+    // %WORKDIR% is the directory of the job, such as for make -C %WORKDIR%
+    #[serde(default, alias = "compile")]
+    pub command: Vec<String>,
+    // the command to run the program
+    // %INPUT% is the source file, %OUTPUT% is the compiled file
+    // and %WORKDIR% is the directory of the job
+    #[serde(default = "default_run")]
+    pub run: Vec<String>,
+    // the time limit of the compiler in microseconds
+    #[serde(default = "default_compile_time_limit")]
+    pub compile_time_limit: u64,
+    // the address space limit of the compiler in bytes, 0 for no limit
+    #[serde(default)]
+    pub compile_memory_limit: u64,
+    // the limits of a case for this language are
+    // limit * multiplier + extra, in microseconds and bytes
+    #[serde(default = "default_multiplier")]
+    pub time_multiplier: f64,
+    #[serde(default = "default_multiplier")]
+    pub memory_multiplier: f64,
+    #[serde(default)]
+    pub extra_time: u64,
+    #[serde(default)]
+    pub extra_memory: u64
 }
 
+fn default_compile_time_limit() -> u64 { 30_000_000 }
+
+fn default_run() -> Vec<String> { vec!["%OUTPUT%".to_string()] }
+
+fn default_multiplier() -> f64 { 1.0 }
+
 impl Language {
     // replace "%OUTPUT%" in the compile commond
     pub fn replace(&mut self, before: &str, after: &str) -> bool {
@@ -64,6 +298,48 @@ impl Language {
         }
         return false;
     }
+    // replace "%GRADER%" in the compile commond with any number of args
+    // the placeholder is removed if there is no arg
+    pub fn replace_with(&mut self, before: &str, after: &[String]) {
+        let mut command = Vec::new();
+        for item in self.command.drain(..) {
+            if item==before {
+                command.extend_from_slice(after);
+            } else {
+                command.push(item);
+            }
+        }
+        self.command = command;
+    }
+    // the time limit of the case in microseconds for this language
+    pub fn time_limit(&self, case: &Case) -> u64 {
+        (case.time_limit as f64 * self.time_multiplier) as u64 + self.extra_time
+    }
+    // the wall clock time limit of the case in microseconds for this language
+    // by default one second more than the cpu time limit rounded up to seconds
+    // as the rlimit of the cpu time, so a program is not killed before it
+    pub fn wall_time_limit(&self, case: &Case) -> u64 {
+        if case.wall_time_limit == 0 {
+            return (self.time_limit(case) + 999_999) / 1_000_000 * 1_000_000 + 1_000_000;
+        }
+        (case.wall_time_limit as f64 * self.time_multiplier) as u64 + self.extra_time
+    }
+    // the memory limit of the case in bytes for this language
+    // 0 is still no limit
+    pub fn memory_limit(&self, case: &Case) -> u64 {
+        if case.memory_limit == 0 {
+            return 0;
+        }
+        (case.memory_limit as f64 * self.memory_multiplier) as u64 + self.extra_memory
+    }
+    // the run command with the placeholders replaced
+    pub fn run_command(&self, input: &str, output: &str, work_dir: &str) -> Vec<String> {
+        self.run.iter().map(|item| {
+            item.replace("%INPUT%", input)
+                .replace("%OUTPUT%", output)
+                .replace("%WORKDIR%", work_dir)
+        }).collect()
+    }
 }
 
 // some simple tests
@@ -79,6 +355,8 @@ mod test {
         let config: Config = serde_json::from_str(&json).expect("Parse failed");
         let output = serde_json::to_string_pretty(&config).unwrap();
         println!("{}", output);
+        // the sandbox is enabled without the section
+        assert!(config.sandbox.enabled);
     }
     #[test]
     fn test_replace() {
@@ -90,5 +368,68 @@ mod test {
         println!("{:?}", &lang.command);
         assert_eq!(lang.command[4], "jobid");
         assert_eq!(lang.command[5], "main.rs");
+        lang.replace_with("main.rs", &["grader.rs".to_string(), "lib.rs".to_string()]);
+        assert_eq!(lang.command[5..], ["grader.rs", "lib.rs"]);
+        lang.replace_with("lib.rs", &[]);
+        assert_eq!(lang.command.len(), 6);
+    }
+    #[test]
+    fn test_run_command() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let rust = &config.languages[0];
+        assert_eq!(rust.run_command("main.rs", "a.out", "job"), vec!["a.out"]);
+        let python = config.languages.iter().find(|x| { x.name=="Python" }).unwrap();
+        assert!(python.command.is_empty());
+        assert_eq!(python.run_command("job/main.py", "a.out", "job"), vec!["python3", "job/main.py"]);
+    }
+    #[test]
+    fn test_limits() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let case = &config.problems[0].cases[0];
+        let rust = &config.languages[0];
+        assert_eq!(rust.time_limit(case), case.time_limit as u64);
+        assert_eq!(rust.memory_limit(case), case.memory_limit as u64);
+        assert_eq!(rust.wall_time_limit(case), case.time_limit as u64 + 1_000_000);
+        let python = config.languages.iter().find(|x| { x.name=="Python" }).unwrap();
+        assert_eq!(python.time_limit(case), case.time_limit as u64 * 3 + 100_000);
+        assert_eq!(python.memory_limit(case), case.memory_limit as u64 * 2);
+        // the default wall clock time limit follows the rounded cpu time limit
+        let mut case = case.clone();
+        case.time_limit = 200_000;
+        assert_eq!(rust.wall_time_limit(&case), 2_000_000);
+        assert_eq!(python.wall_time_limit(&case), 2_000_000);
+        case.time_limit = 1_000_000;
+        assert_eq!(rust.wall_time_limit(&case), 2_000_000);
+    }
+    #[test]
+    fn test_packing() {
+        let json = fs::read_to_string("./tests/cases/adv_05_packed_judging.config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let groups = config.problems[0].packing();
+        assert_eq!(groups, vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9, 10]]);
+
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let groups = config.problems[0].packing();
+        assert_eq!(groups, vec![vec![1], vec![2]]);
+
+        // a wrong index fails the parsing of the config
+        let with_packing = |packing: &str| {
+            json.replace("\"misc\": {}", &format!("\"misc\": {{\"packing\": {}}}", packing))
+        };
+        assert!(serde_json::from_str::<Config>(&with_packing("[[2], [1]]")).is_ok());
+        assert!(serde_json::from_str::<Config>(&with_packing("[[1, 3]]")).is_err());
+        assert!(serde_json::from_str::<Config>(&with_packing("[[0]]")).is_err());
+        assert!(serde_json::from_str::<Config>(&with_packing("[[1, 2], [2]]")).is_err());
+        assert!(serde_json::from_str::<Config>(&with_packing("[[1, 1]]")).is_err());
+        assert!(serde_json::from_str::<Config>(&with_packing("\"all\"")).is_err());
+    }
+    #[test]
+    fn test_unknown_type() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let json = json.replace("\"standard\"", "\"lenient\"");
+        assert!(serde_json::from_str::<Config>(&json).is_err());
     }
 }
@@ -1,5 +1,6 @@
 
 use std::cmp::{Ord, Ordering};
+use std::collections::HashMap;
 use actix_web::{
     get, post, web, 
     Responder, 
@@ -8,11 +9,11 @@ use actix_web::{
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
-use crate::{JOBDATA, config, AppError, job::{JobInfo, Job}, User};
+use crate::{JOBDATA, config, AppError, job::{JobInfo, Job}, User, RunResult};
 
 
 // this struct represent the json content of the contest http request
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ContestInfo {
     pub id: u32,
     pub name: String,
@@ -74,7 +75,7 @@ pub async fn get_contests() -> impl Responder {
     let job_data = JOBDATA.clone();
     let job_data_inner = job_data.lock().unwrap();
 
-    let mut temp_user_list: Vec<ContestInfo> = job_data_inner.contests_list.iter().map(|x| {x.0.clone()}).collect();
+    let mut temp_user_list: Vec<ContestInfo> = job_data_inner.storage().contest_list().iter().map(|x| {x.0.clone()}).collect();
     temp_user_list.sort_by_key(|x| {x.id});
     log::info!(target: "get_contests", "Get contests list");
     return HttpResponse::Ok().json(temp_user_list);
@@ -85,7 +86,7 @@ pub async fn get_contests() -> impl Responder {
 pub async fn get_contest_id(id: web::Path<u32>) -> Result<HttpResponse, AppError> {
     let job_data = JOBDATA.clone();
     let job_data_inner = job_data.lock().unwrap();
-    let res = job_data_inner.find_contest(*id)?;
+    let res = job_data_inner.storage().find_contest(*id)?;
 
     log::info!(target: "get_contest_id", "Get contest {}", res.0.id);
     return Ok(HttpResponse::Ok().json(res.0.clone()));
@@ -177,6 +178,42 @@ impl SortKey {
 
 
 
+// the min time of each case among the jobs
+// the index of the result is the case index starting from 1
+fn min_case_time(jobs: &[&Job], case_count: usize) -> Vec<Option<u32>> {
+    let mut min_time: Vec<Option<u32>> = vec![None; case_count + 1];
+    for job in jobs.iter() {
+        for case in job.case_res.iter().skip(1) {
+            let idx = case.id as usize;
+            if idx > case_count || case.result != RunResult::Accepted {
+                continue;
+            }
+            let time = case.time.max(1);
+            min_time[idx] = Some(min_time[idx].map_or(time, |x| { x.min(time) }));
+        }
+    }
+    min_time
+}
+
+// the time share of a job of the dynamic ranking problem
+// each accepted case gets ratio * score * min_time / time
+fn dynamic_score(job: &Job, problem: &config::Problem, ratio: f32, min_time: &[Option<u32>]) -> f32 {
+    if job.result != RunResult::Accepted {
+        return 0.0;
+    }
+    let mut score = 0.0;
+    for (i, case) in problem.cases.iter().enumerate() {
+        let res = match job.case_res.get(i + 1) {
+            Some(res) => res,
+            None => continue,
+        };
+        if let (RunResult::Accepted, Some(Some(min))) = (res.result, min_time.get(i + 1)) {
+            score += case.score * ratio * (*min as f32) / (res.time.max(1) as f32);
+        }
+    }
+    score
+}
+
 // get the ranklist 
 #[get("/contests/{contestid}/ranklist")]
 pub async fn get_contest_ranklist(
@@ -186,20 +223,21 @@ pub async fn get_contest_ranklist(
     ) -> Result<HttpResponse, AppError> {
     let job_data = JOBDATA.clone();
     let job_data_inner = job_data.lock().unwrap();
+    let storage = job_data_inner.storage();
 
     let mut user: Vec<u32> = Vec::new();
     let mut problem: Vec<u32> = Vec::new();
 
     // global contest list
     if *id==0 {
-        job_data_inner.user_list.iter().for_each(|x| {
+        storage.user_list().iter().for_each(|x| {
             user.push(x.id);
         });
         config.problems.iter().for_each(|x| {
             problem.push(x.id);
         });
     } else { // specify a contest id
-        let contest = job_data_inner.find_contest(*id)?;
+        let contest = storage.find_contest(*id)?;
         user = contest.0.user_ids.clone();
         problem = contest.0.problem_ids.clone();
 
@@ -208,24 +246,42 @@ pub async fn get_contest_ranklist(
     log::info!(target: "get_contest_ranklist", "Get contest {} ranklist", id);
 
     // a closure the get the score of one user
-    let score_rule = |a: &Job, b: &Job| {
+    // a submission is compared with its total score including the time share
+    let score_rule = |a: &(f32, &Job), b: &(f32, &Job)| {
         match query.scoring_rule {
             Scorerule::latest => {
-                return a.created_time.cmp(&b.created_time);
+                return a.1.created_time.cmp(&b.1.created_time);
             },
             Scorerule::highest => {
-                if a.score==b.score {
-                    return b.created_time.cmp(&a.created_time);
+                if a.0==b.0 {
+                    return b.1.created_time.cmp(&a.1.created_time);
                 }
-                return a.score.partial_cmp(&b.score).unwrap();
+                return a.0.partial_cmp(&b.0).unwrap();
             },
         }
     };
 
 
+    // the min time of each case among the accepted submissions
+    // of the dynamic ranking problems
+    // a stored contest may have a problem removed from the config, which has no time share
+    let mut min_time: HashMap<u32, Vec<Option<u32>>> = HashMap::new();
+    for problem_id in problem.iter() {
+        let pro = match config.problems.iter().find(|x| { x.id==*problem_id }) {
+            Some(pro) => pro,
+            None => continue,
+        };
+        if pro.dynamic_ranking_ratio().is_some() {
+            let accepted: Vec<&Job> = storage.job_list().into_iter().filter(|x| {
+                x.info.problem_id==*problem_id && x.info.contest_id == *id && x.result==RunResult::Accepted
+            }).collect();
+            min_time.insert(*problem_id, min_case_time(&accepted, pro.cases.len()));
+        }
+    }
+
     let mut res: Vec<(ContestRes, SortKey)> = Vec::new();
     for user_id in user.iter() {
-        let user = job_data_inner.find_user(*user_id).unwrap();
+        let user = storage.find_user(*user_id).unwrap();
         let mut score: Vec<f32> = Vec::new();
         let mut total_score = 0.0;
         let mut time: Option<DateTime<Utc>> = None;
@@ -233,15 +289,25 @@ pub async fn get_contest_ranklist(
         for problem_id in problem.iter() {
 
             // find add submission of the user and the problem
-            let submission_set = job_data_inner.job_list.iter().filter(|x| {
+            let submission_set = storage.job_list().into_iter().filter(|x| {
                 x.info.user_id==*user_id && x.info.problem_id==*problem_id && x.info.contest_id == *id
             });
+            // the total score of each submission
+            // add the time share of the dynamic ranking problem
+            let pro = config.problems.iter().find(|x| { x.id==*problem_id });
+            let totals = submission_set.clone().map(|job| {
+                match (pro, min_time.get(problem_id)) {
+                    (Some(pro), Some(min_time)) => {
+                        let ratio = pro.dynamic_ranking_ratio().unwrap_or(0.0);
+                        (job.score + dynamic_score(job, pro, ratio, min_time), job)
+                    },
+                    _ => (job.score, job)
+                }
+            });
             // use the score_rule to get the result from the submission_set
-            let (pro_score, created_time) = submission_set.clone()
+            let (pro_score, created_time) = totals
             .max_by( |a, b| { score_rule(a, b) } )
-            .map_or((0.0, None), |job| {
-                (job.score, Some(job.created_time))
-            });
+            .map_or((0.0, None), |(total, job)| { (total, Some(job.created_time)) });
 
             if let Some(update) = created_time {
                 match time {
@@ -1,37 +1,55 @@
 use std::fs::{write, File, self, OpenOptions};
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
-use std::process::{Command, Stdio};
-use std::{io, usize};
+use std::process::{Child, Command, Stdio};
+use std::io;
 
 use serde::Deserialize;
 use serde::Serialize;
 
 use serde_json::json;
-use wait_timeout::ChildExt;
 use std::time::Duration;
 
 use chrono::prelude::*;
 
-use crate::{config, State};
+use crate::{archive, config, process, sandbox, State};
 use crate::RunResult;
 use crate::CaseResult;
 use crate::Response;
 
 const DIRPREFIX: &str = "./tmp";
+// the default time limit of the special judge in microseconds
+const SPJ_TIME_LIMIT: u64 = 5_000_000;
+// the max size in bytes of the stderr shown in the case info
+const STDERR_EXCERPT: usize = 1024;
+// the size in bytes of the end of a log searched for a failed allocation
+const LOG_TAIL: usize = 4096;
+// the limits of a custom run before the multipliers of the language
+// in microseconds and bytes
+const RUN_TIME_LIMIT: u32 = 1_000_000;
+const RUN_MEMORY_LIMIT: u32 = 256 * 1024 * 1024;
+const RUN_OUTPUT_LIMIT: u64 = 64 * 1024;
 
 
 // the struct represent the json content from the post job http request 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct JobInfo {
+    // may be left out when the archive is given
+    #[serde(default)]
     pub source_code: String,
     pub language: String,
     pub user_id: u32,
     pub contest_id: u32,
-    pub problem_id: u32
+    pub problem_id: u32,
+    // the base64 tar or zip archive of a multi-file submission
+    // it is extracted to the job directory instead of writing the source code
+    // it is not sent back in the responses and it is stored on its own, see FileStorage
+    #[serde(default, skip_serializing)]
+    pub archive: Option<String>
 }
 
 // use this struct to run a job and get a response
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
     pub user_name: String,
     pub job_id: u32,
@@ -41,7 +59,25 @@ pub struct Job {
     pub updated_time: DateTime<Utc>,
     pub state: State,
     pub result: RunResult,
-    pub case_res: Vec<CaseResult>
+    pub case_res: Vec<CaseResult>,
+    // a custom run has its own directory and no problem
+    #[serde(skip)]
+    custom_run: bool
+}
+
+// the result of a custom run
+// info is the compiler output or the reason of the result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunOutput {
+    pub result: RunResult,
+    pub info: String,
+    pub stdout: String,
+    pub stderr: String,
+    pub time: u32,
+    pub cpu_time: u32,
+    pub memory: u32,
+    pub time_limit: u64,
+    pub memory_limit: u64
 }
 
 impl Job {
@@ -51,13 +87,20 @@ impl Job {
             job_id,
             info: info.clone(),
             score: 0.0,
-            created_time: DateTime::default(),
-            updated_time: DateTime::default(),
+            created_time: Utc::now(),
+            updated_time: Utc::now(),
             state: State::Queueing,
             result: RunResult::Waiting,
-            case_res: Vec::new()
+            case_res: Vec::new(),
+            custom_run: false
         }
     }
+    // a job for a custom run, which is not stored
+    pub fn new_custom_run(run_id: u32, info: &JobInfo) -> Self {
+        let mut job = Self::new("", run_id, info);
+        job.custom_run = true;
+        return job;
+    }
 
     // sparate the run commond to several parts
     // first init and the compile the souce code 
@@ -73,42 +116,128 @@ impl Job {
         if !self.init(config) {
             self.state = State::Finished;
             self.result = RunResult::SystemError;
+            self.updated_time = Utc::now();
             return self.response();
         }
         // init success set the state and result
         self.result = RunResult::Running;
         self.state = State::Running;
+        self.updated_time = Utc::now();
 
         // compile failed set state and result return
         if !self.compile_source_code(config, 0) {
             self.state = State::Finished;
             self.result = RunResult::CompilationError;
+            self.updated_time = Utc::now();
             return self.response();
         }
         // compile success
         self.result = RunResult::CompilationSuccess;
 
-        let mut ans = true;
-        // run and test each case of the problem
-        for (i, case) in problem.cases.iter().enumerate() {
-            if !self.run_one_case(problem, case, i+1) {
-                self.state = State::Finished;
-                self.result = self.case_res[i+1].result;
-                return self.response();
+        // the first case result which is not accepted
+        let mut failed: Option<RunResult> = None;
+        // for dynamic ranking problems only the correctness share is scored here
+        // the time share is scored when the ranklist is computed
+        let ratio = problem.dynamic_ranking_ratio().unwrap_or(0.0);
+        // run and test the cases group by group
+        // a group only scores if all of its cases are accepted
+        // the remaining cases of a group are skipped after the first failure
+        for group in problem.packing() {
+            let mut group_score = 0.0;
+            let mut passed = true;
+            for idx in group.iter().copied() {
+                if !passed {
+                    self.case_res[idx].result = RunResult::Skipped;
+                    continue;
+                }
+                let case = &problem.cases[idx-1];
+                self.run_one_case(config, problem, case, idx);
+                if self.case_res[idx].result==RunResult::Accepted {
+                    self.case_res[idx].score *= 1.0 - ratio;
+                    group_score += self.case_res[idx].score;
+                } else {
+                    passed = false;
+                    failed.get_or_insert(self.case_res[idx].result);
+                }
             }
-            if self.case_res[i+1].result==RunResult::Accepted {
-                self.score += case.score;
+            if passed {
+                self.score += group_score;
             } else {
-                ans = false;
+                // the cases of a failed group earn nothing
+                for idx in group.iter() {
+                    self.case_res[*idx].score = 0.0;
+                }
             }
         }
         self.state = State::Finished;
-        if ans {
-            self.result = RunResult::Accepted;
+        self.updated_time = Utc::now();
+        self.result = failed.unwrap_or(RunResult::Accepted);
+        return self.response();
+    }
+    // run the source code once on the input for a custom test
+    // the case has the default limits and its output is compared with itself
+    // so an accepted run only means that the program exits normally
+    // the directory is removed after the run
+    pub fn run_custom(&mut self, config: &config::Config, input: &str) -> RunOutput {
+        let language = config.languages.iter().find(
+            |item| {item.name==self.info.language}
+            ).unwrap();
+        let case = config::Case {
+            score: 0.0,
+            input_file: self.path("input"),
+            answer_file: self.path("output"),
+            time_limit: RUN_TIME_LIMIT,
+            memory_limit: RUN_MEMORY_LIMIT,
+            wall_time_limit: 0,
+            output_limit: RUN_OUTPUT_LIMIT
+        };
+        let problem = config::Problem {
+            id: self.info.problem_id,
+            name: "custom run".to_string(),
+            problem_type: config::ProblemType::standard,
+            misc: json!({}),
+            cases: vec![case.clone()]
+        };
+        self.case_res = vec![CaseResult::new(0), CaseResult::new(1)];
+        self.case_res[1].time_limit = language.time_limit(&case);
+        self.case_res[1].wall_time_limit = language.wall_time_limit(&case);
+        self.case_res[1].memory_limit = language.memory_limit(&case);
+        self.state = State::Running;
+
+        let try_do = || -> io::Result<()> {
+            self.clear();
+            fs::create_dir_all(self.work_dir())?;
+            write(self.path("input"), input)?;
+            Ok(())
+        };
+        let mut idx = 1;
+        if let Err(e) = try_do() {
+            log::info!(target: "Job::run_custom", "System io error {}", e);
+            self.case_res[1].result = RunResult::SystemError;
+        } else if !self.compile_source_code(config, 0) {
+            idx = 0;
         } else {
-            self.result = RunResult::WrongAnswer;
+            self.run_one_case(config, &problem, &case, 1);
         }
-        return self.response();
+        let read = |name: &str| -> String {
+            fs::read(self.path(name)).map(|out| { String::from_utf8_lossy(&out).to_string() }).unwrap_or_default()
+        };
+        let res = &self.case_res[idx];
+        let output = RunOutput {
+            result: if idx==0 { RunResult::CompilationError } else { res.result },
+            info: res.info.clone(),
+            stdout: if idx==0 { String::new() } else { read("output") },
+            stderr: if idx==0 { String::new() } else { read("case_1.stderr") },
+            time: res.time,
+            cpu_time: res.cpu_time,
+            memory: res.memory,
+            time_limit: self.case_res[1].time_limit,
+            memory_limit: self.case_res[1].memory_limit
+        };
+        self.clear();
+        self.state = State::Finished;
+        self.result = output.result;
+        return output;
     }
     // check valid of the job with the config
     pub fn is_valid(&self, config: &config::Config) -> bool {
@@ -133,16 +262,40 @@ impl Job {
             cases: self.case_res.clone()
         }
     }
+    // reset the result of the job
+    // and put it back to the queueing state
+    pub fn reset(&mut self, config: &config::Config) {
+        self.updated_time = Utc::now();
+        self.score = 0.0;
+        self.state = State::Queueing;
+        self.result = RunResult::Waiting;
+        self.case_res.clear();
+        let problem = config.problems.iter().find(
+            |item| { item.id==self.info.problem_id }
+            ).unwrap();
+        // init case res with id and waiting result
+        for i in 0..=problem.cases.len() {
+            self.case_res.push(CaseResult::new(i as u32));
+        }
+        // show the limits of each case for the language
+        if let Some(language) = config.languages.iter().find(|item| {item.name==self.info.language}) {
+            for (i, case) in problem.cases.iter().enumerate() {
+                self.case_res[i+1].time_limit = language.time_limit(case);
+                self.case_res[i+1].wall_time_limit = language.wall_time_limit(case);
+                self.case_res[i+1].memory_limit = language.memory_limit(case);
+            }
+        }
+    }
     // init for the job 
     // clean the directory
-    // set the init field value
+    // reset the result of the job
     // return true if init success 
     // false othewise
     fn init(&mut self, config: &config::Config) -> bool {
         // try clean the directory
         let try_do = || -> io::Result<()> {
             self.clear();
-            let path = format!("{}/job_{}", DIRPREFIX, self.job_id);
+            let path = self.work_dir();
             if !Path::new(&path).is_dir() {
                 fs::create_dir(&path)?;
             }
@@ -156,25 +309,12 @@ impl Job {
         }
 
         assert!(self.is_valid(config));
-        // set the init value of the field
-        self.created_time = Utc::now();
-        self.updated_time = Utc::now();
-        self.score = 0.0;
-        self.state = State::Queueing;
-        self.result = RunResult::Waiting;
-        self.case_res.clear();
-        let problem = config.problems.iter().find(
-            |item| { item.id==self.info.problem_id }
-            ).unwrap();
-        // init case res with id and waiting result
-        for i in 0..=problem.cases.len() {
-            self.case_res.push(CaseResult::new(i as u32));
-        }
+        self.reset(config);
         return true;
     }
     // clear the directory
     fn clear(&self) {
-        let path = format!("{}/job_{}", DIRPREFIX, self.job_id);
+        let path = self.work_dir();
         if Path::new(&path).is_dir() {
             fs::remove_dir_all(&path).expect("Clear failed");
         }
@@ -182,61 +322,162 @@ impl Job {
     // run one case of the problem
     // return true if success 
     // false otherwise
-    fn run_one_case(&mut self, problem: &config::Problem, case: &config::Case, caseidx: usize) -> bool {
+    fn run_one_case(&mut self, config: &config::Config, problem: &config::Problem, case: &config::Case, caseidx: usize) -> bool {
         let mut ret = false;
         let mut info = String::new();
+        let mut usage = None;
+        let mut fraction = None;
+        let mut stderr = String::new();
+        // the limits of the case for the language
+        let language = config.languages.iter().find(
+            |item| {item.name==self.info.language}
+            ).unwrap();
+        let time_limit = Duration::from_micros(language.time_limit(case));
+        let wall_time_limit = Duration::from_micros(language.wall_time_limit(case));
+        let memory_limit = language.memory_limit(case);
+        let interactive = problem.problem_type == config::ProblemType::interactive;
         // try run one case
         let mut try_do = || -> io::Result<RunResult> {
-            // input and output file
-            let input = File::open(&case.input_file)?;
-            let output = OpenOptions::new().read(true).write(true).truncate(true).create(true)
-                .open(self.path("output"))?;
-            // creat the process
-            let mut process = Command::new(self.path("a.out"))
-                .stdin(input)
-                .stdout(Stdio::from(output)).spawn()?;
-            // wait timeout of the process
-            let res = process.wait_timeout(Duration::from_micros(case.time_limit as u64))?;
-            match res {
-                // exit 
-                Some(exit) => {
-                    // exit successs
-                    if exit.success() {
-                        ret = true;
-                        // problem with special_judge argument
-                        if let Some(spj) = problem.misc.get("special_judge") {
-                            let mut args: Vec<String> = serde_json::from_value(spj.clone()).unwrap();
-                            let pos = args.iter_mut().find(|item| {*item=="%OUTPUT%"}).unwrap();
-                            *pos = self.path("output");
-                            let pos = args.iter_mut().find(|item| {*item=="%ANSWER%"}).unwrap();
-                            *pos = case.answer_file.clone();
-                            let process = Command::new(&args[0])
-                                .args(&args[1..])
-                                .stdout(Stdio::piped()).spawn()?;
-                            let mut res = String::new();
-                            process.stdout.unwrap().read_to_string(&mut res).unwrap();
-                            let res: Vec<&str> = res.split('\n').map(|x| {x}).collect();
-                            let ret: RunResult = serde_json::from_value(
-                                json!(res[0].to_string())
-                                ).unwrap();
-                            info = res[1].to_string();
-                            return Ok(ret);
-                        } else {  // problem with out special_judge argument
-                            let ans = fs::read_to_string(&case.answer_file)?;
-                            let out = fs::read_to_string(self.path("output"))?;
-                            if ans==out { return Ok(RunResult::Accepted);}
-                            else { return Ok(RunResult::WrongAnswer);}
+            // creat the process of the run command with the time, memory and output limit
+            // the paths are absolute since a file-I/O program runs in the work dir
+            let work_dir = fs::canonicalize(self.work_dir())?.to_string_lossy().to_string();
+            let run = language.run_command(&format!("{}/{}", work_dir, language.file_name), &format!("{}/a.out", work_dir), &work_dir);
+            if run.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty run command"));
+            }
+            let mut command = Command::new(&run[0]);
+            command.args(&run[1..]);
+            // an interactive program talks with the interactor through the pipes
+            // otherwise it reads the input file and writes the output file
+            // or reads and writes the named files of a file-I/O problem in the work dir
+            if interactive {
+                command.stdin(Stdio::piped()).stdout(Stdio::piped());
+            } else {
+                let input = match problem.input_file_name() {
+                    Some(name) => {
+                        fs::copy(&case.input_file, self.job_file(name)?)?;
+                        Stdio::null()
+                    },
+                    None => Stdio::from(File::open(&case.input_file)?),
+                };
+                // do not judge the output file left by the last case
+                if let Some(name) = problem.output_file_name() {
+                    if let Err(err) = fs::remove_file(self.job_file(name)?) {
+                        if err.kind() != io::ErrorKind::NotFound {
+                            return Err(err);
                         }
                     }
-                    // exit with error 
-                    return Ok(RunResult::RuntimeError);
+                }
+                let output = OpenOptions::new().read(true).write(true).truncate(true).create(true)
+                    .open(self.path("output"))?;
+                command.stdin(input).stdout(Stdio::from(output));
+                if problem.input_file_name().is_some() || problem.output_file_name().is_some() {
+                    command.current_dir(&work_dir);
+                }
+            }
+            // the stderr is read through a pipe while the program runs
+            // so it is limited on its own and not by the output limit of the case
+            // the program and the processes it starts are in a new process group
+            // so that all of them can be killed
+            command.stderr(Stdio::piped());
+            process::set_process_group(&mut command);
+            process::set_memory_limit(&mut command, memory_limit);
+            process::set_cpu_time_limit(&mut command, time_limit);
+            process::set_output_limit(&mut command, case.output_limit);
+            if config.sandbox.enabled {
+                sandbox::apply(&mut command, config, Path::new(&self.work_dir()))?;
+            }
+            let mut process = command.spawn()?;
+            let capture = process::capture(process.stderr.take().unwrap(), config.server.stderr_limit, LOG_TAIL);
+            let interactor = match interactive {
+                true => match self.spawn_interactor(problem, case, &mut process) {
+                    Ok(interactor) => Some(interactor),
+                    Err(err) => {
+                        process::kill_process_group(&process);
+                        process.wait()?;
+                        capture.finish();
+                        return Err(err);
+                    }
                 },
-                // timeout
-                None => {
-                    process.kill()?;
-                    return Ok(RunResult::TimeLimitExceeded);
+                false => None,
+            };
+            // wait timeout of the process and get the resource usage
+            let res = process::wait_with_usage(&mut process, wall_time_limit);
+            process::kill_process_group(&process);
+            let captured = capture.finish();
+            // the interactor gets the end of file once the program is gone
+            let interaction = interactor.map(|interactor| { self.wait_interactor(problem, interactor) });
+            let res = res?;
+            // keep the beginning of the stderr in the job directory and show a shorter part
+            // the end tells whether an allocation failed
+            write(self.path(&format!("case_{}.stderr", caseidx)), &captured.head)?;
+            stderr = excerpt(&captured.head, captured.size, STDERR_EXCERPT);
+            let stderr_tail = captured.tail;
+            // the named output file is judged instead of the stdout
+            // a missing file or anything other than a regular file is an empty output
+            if let (false, Some(name)) = (interactive, problem.output_file_name()) {
+                let path = self.job_file(name)?;
+                match fs::symlink_metadata(&path) {
+                    Ok(meta) if meta.is_file() => fs::rename(&path, self.path("output"))?,
+                    _ => write(self.path("output"), "")?,
                 }
             }
+            usage = Some(res.usage);
+            // killed because of the wall clock time limit
+            // or used up the cpu time limit, when SIGXCPU or SIGKILL is received
+            if res.timed_out {
+                info = "wall clock time limit exceeded".to_string();
+                return Ok(RunResult::TimeLimitExceeded);
+            }
+            if process::is_cpu_time_exceeded(&res, time_limit) {
+                info = match res.status.success() {
+                    true => "cpu time limit exceeded".to_string(),
+                    false => process::describe_status(&res.status),
+                };
+                return Ok(RunResult::TimeLimitExceeded);
+            }
+            // the interactor decides the verdict of a finished program
+            // its wrong answer also explains a program that fails after the interaction
+            if let Some((verdict, msg, frac)) = interaction {
+                if res.status.success() || verdict == RunResult::WrongAnswer {
+                    ret = res.status.success();
+                    info = msg;
+                    fraction = frac;
+                    return Ok(verdict);
+                }
+            } else {
+                let output_size = fs::metadata(self.path("output"))?.len();
+                if process::is_output_limit_exceeded(&res, output_size, case.output_limit) {
+                    if !res.status.success() {
+                        info = process::describe_status(&res.status);
+                    }
+                    return Ok(RunResult::OutputLimitExceeded);
+                }
+            }
+            // exit successs
+            if res.status.success() {
+                ret = true;
+                // problem with special_judge argument
+                if let Some(spj) = problem.misc.get("special_judge") {
+                    let (ret, msg, frac) = self.special_judge(problem, spj, case);
+                    info = msg;
+                    fraction = frac;
+                    return Ok(ret);
+                } else {  // problem with out special_judge argument
+                    let ans = fs::read_to_string(&case.answer_file)?;
+                    let out = fs::read_to_string(self.path("output"))?;
+                    let strict = problem.problem_type == config::ProblemType::strict;
+                    if compare(&ans, &out, strict) { return Ok(RunResult::Accepted);}
+                    else { return Ok(RunResult::WrongAnswer);}
+                }
+            }
+            // exit with error, the signal or the exit code is shown in the info
+            // a process failed because of the memory limit is MLE
+            info = process::describe_status(&res.status);
+            if process::is_out_of_memory(&res, memory_limit, &stderr_tail) {
+                return Ok(RunResult::MemoryLimitExceeded);
+            }
+            return Ok(RunResult::RuntimeError);
         };
         let res = try_do().unwrap_or_else(|err| {
             log::info!(target: "Job::run_one_case", "System io error {}", err);
@@ -244,15 +485,177 @@ impl Job {
         });
 
         // set the case result of the caseidx
+        // an accepted case earns the full score unless the special judge gives a fraction
+        let fraction = fraction.unwrap_or(if res==RunResult::Accepted { 1.0 } else { 0.0 });
         self.case_res[caseidx].result = res;
+        self.case_res[caseidx].score = case.score * fraction;
+        // add the beginning of the stderr to the info
+        if config.server.show_stderr && !stderr.is_empty() {
+            if !info.is_empty() {
+                info.push('\n');
+            }
+            info.push_str(&stderr);
+        }
         self.case_res[caseidx].info = info;
+        if let Some(usage) = usage {
+            self.case_res[caseidx].time = usage.wall_time.as_micros() as u32;
+            self.case_res[caseidx].cpu_time = usage.cpu_time.as_micros() as u32;
+            self.case_res[caseidx].memory = usage.memory as u32;
+        }
         return ret;
     }
+    // run the special judge of the problem on the output of one case
+    // the checker prints the verdict on the first line and a message on the second line
+    // an optional third line is the fraction of the case score to earn, from 0 to 1
+    // the fraction is only given with Accepted, so a partly scored case does not fail its group
+    // any failure of the checker is reported as SPJ Error with the reason
+    fn special_judge(&self, problem: &config::Problem, spj: &serde_json::Value, case: &config::Case) -> (RunResult, String, Option<f32>) {
+        let args = match self.judge_command(spj, case) {
+            Some(args) => args,
+            None => return (RunResult::SpjError, "invalid special judge command".to_string(), None),
+        };
+        let time_limit = judge_time_limit(problem);
+
+        // write the checker output to a file so a large output can not block the pipe
+        let try_do = || -> io::Result<(process::WaitResult, String)> {
+            let output = OpenOptions::new().read(true).write(true).truncate(true).create(true)
+                .open(self.path("spj_output"))?;
+            let mut process = Command::new(&args[0])
+                .args(&args[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::from(output))
+                .spawn()?;
+            let res = process::wait_with_usage(&mut process, Duration::from_micros(time_limit))?;
+            let mut out = String::new();
+            File::open(self.path("spj_output"))?.read_to_string(&mut out)?;
+            Ok((res, out))
+        };
+        let (res, out) = match try_do() {
+            Ok(res) => res,
+            Err(err) => return (RunResult::SpjError, format!("special judge failed to run: {}", err), None),
+        };
+        if res.timed_out {
+            return (RunResult::SpjError, "special judge timeout".to_string(), None);
+        }
+        if !res.status.success() {
+            return (RunResult::SpjError, format!("special judge exited with {}", res.status), None);
+        }
+
+        let mut lines = out.lines();
+        let verdict = lines.next().unwrap_or("").trim();
+        let ret = match serde_json::from_value::<RunResult>(json!(verdict)) {
+            Ok(ret @ (RunResult::Accepted | RunResult::WrongAnswer)) => ret,
+            _ => return (RunResult::SpjError, format!("unknown special judge verdict \"{}\"", verdict), None),
+        };
+        let msg = match lines.next() {
+            Some(msg) => msg.to_string(),
+            None => return (RunResult::SpjError, "special judge message is missing".to_string(), None),
+        };
+        let fraction = match parse_fraction(lines.next()) {
+            Ok(fraction) => fraction,
+            Err(line) => return (RunResult::SpjError, format!("invalid special judge score \"{}\"", line), None),
+        };
+        if fraction.is_some() && ret != RunResult::Accepted {
+            return (RunResult::SpjError, "special judge score without Accepted".to_string(), None);
+        }
+        (ret, msg, fraction)
+    }
+    // start the interactor of the problem connected to the program
+    // the program reads what the interactor writes and the other way round
+    // the interactor writes its message to stderr, which is kept in a file
+    fn spawn_interactor(&self, problem: &config::Problem, case: &config::Case, program: &mut Child) -> io::Result<Child> {
+        let args = problem.misc.get("interactor")
+            .and_then(|interactor| { self.judge_command(interactor, case) })
+            .ok_or_else(|| { io::Error::new(io::ErrorKind::InvalidInput, "invalid interactor command") })?;
+        let output = OpenOptions::new().read(true).write(true).truncate(true).create(true)
+            .open(self.path("interactor_output"))?;
+        // the command keeps the pipes open until it is dropped
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..])
+            .stdin(Stdio::from(program.stdout.take().unwrap()))
+            .stdout(Stdio::from(program.stdin.take().unwrap()))
+            .stderr(Stdio::from(output));
+        process::set_process_group(&mut command);
+        return command.spawn();
+    }
+    // wait the interactor and get the verdict from it
+    // exit code 0 is accepted, 1 or 2 is wrong answer, anything else is SPJ Error
+    // the first line of its message is shown in the info
+    // and an optional second line is the fraction of the case score to earn, from 0 to 1
+    // which is only given with exit code 0
+    fn wait_interactor(&self, problem: &config::Problem, mut interactor: Child) -> (RunResult, String, Option<f32>) {
+        let time_limit = judge_time_limit(problem);
+        let mut try_do = || -> io::Result<(process::WaitResult, String)> {
+            let res = process::wait_with_usage(&mut interactor, Duration::from_micros(time_limit));
+            process::kill_process_group(&interactor);
+            let res = res?;
+            let mut out = String::new();
+            File::open(self.path("interactor_output"))?.read_to_string(&mut out)?;
+            Ok((res, out))
+        };
+        let (res, out) = match try_do() {
+            Ok(res) => res,
+            Err(err) => return (RunResult::SpjError, format!("interactor failed to run: {}", err), None),
+        };
+        if res.timed_out {
+            return (RunResult::SpjError, "interactor timeout".to_string(), None);
+        }
+        let ret = match res.status.code() {
+            Some(0) => RunResult::Accepted,
+            Some(1 | 2) => RunResult::WrongAnswer,
+            _ => return (RunResult::SpjError, format!("interactor exited with {}", res.status), None),
+        };
+        let mut lines = out.lines();
+        let msg = lines.next().unwrap_or("").to_string();
+        let fraction = match parse_fraction(lines.next()) {
+            Ok(fraction) => fraction,
+            Err(line) => return (RunResult::SpjError, format!("invalid interactor score \"{}\"", line), None),
+        };
+        if fraction.is_some() && ret != RunResult::Accepted {
+            return (RunResult::SpjError, "interactor score without Accepted".to_string(), None);
+        }
+        (ret, msg, fraction)
+    }
+    // the command of the special judge or the interactor
+    // %INPUT%, %OUTPUT% and %ANSWER% are replaced by the files of the case
+    fn judge_command(&self, command: &serde_json::Value, case: &config::Case) -> Option<Vec<String>> {
+        let mut args: Vec<String> = serde_json::from_value(command.clone()).ok()?;
+        if args.is_empty() {
+            return None;
+        }
+        for arg in args.iter_mut() {
+            match arg.as_str() {
+                "%INPUT%" => *arg = case.input_file.clone(),
+                "%OUTPUT%" => *arg = self.path("output"),
+                "%ANSWER%" => *arg = case.answer_file.clone(),
+                _ => {}
+            }
+        }
+        return Some(args);
+    }
     // the root temp diectory of the job
+    // custom runs have their own ids and directories
+    fn work_dir(&self) -> String {
+        if self.custom_run {
+            return format!("{}/run_{}", DIRPREFIX, &self.job_id);
+        }
+        format!("{}/job_{}", DIRPREFIX, &self.job_id)
+    }
+    // the file in the root temp diectory of the job
     fn path(&self, filename: &str) -> String {
-        format!("{}/job_{}/{}", DIRPREFIX, &self.job_id, filename)
+        format!("{}/{}", self.work_dir(), filename)
+    }
+    // the file of the given name in the root temp diectory of the job
+    // the name from the problem must not lead out of the directory
+    fn job_file(&self, name: &str) -> io::Result<String> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid file name {}", name)));
+        }
+        return Ok(self.path(name));
     }
     // compile source code
+    // the source code or the archive and the grader files are written to the job directory even if there is no compile step
+    // the output of the compiler is kept in the info of case 0
     fn compile_source_code(&mut self, config: &config::Config, caseidx: usize) -> bool {
 
         let mut ret = false;
@@ -265,13 +668,79 @@ impl Job {
             // replace compile commond with the output file and a.out
             language.replace("%OUTPUT%", &self.path("a.out"));
             language.replace("%INPUT%", &self.path(&language.file_name));
-            write(self.path(&language.file_name), &self.info.source_code)?;
+            language.replace_with("%WORKDIR%", &[self.work_dir()]);
+            if let Some(archive) = &self.info.archive {
+                // a broken archive is the fault of the submission
+                if let Err(err) = archive::extract(archive, Path::new(&self.work_dir()), &config.server) {
+                    if err.kind() != io::ErrorKind::InvalidData {
+                        return Err(err);
+                    }
+                    self.case_res[caseidx].info = format!("invalid archive: {}", err);
+                    return Ok(RunResult::CompilationError);
+                }
+            } else {
+                write(self.path(&language.file_name), &self.info.source_code)?;
+            }
+            // copy the grader files of the problem to the job directory
+            // the ones with the extension of the source file replace %GRADER%
+            // the others such as headers are only copied
+            // a custom run has no problem and no grader
+            let problem = config.problems.iter().find(
+                |item| { item.id==self.info.problem_id && !self.custom_run }
+                );
+            let extension = Path::new(&language.file_name).extension();
+            let mut graders = Vec::new();
+            for file in problem.map(|problem| { problem.graders(&language.name) }).unwrap_or_default() {
+                let name = Path::new(&file).file_name().and_then(|name| name.to_str()).unwrap_or("");
+                if name==language.file_name {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("grader {} has the name of the source file", file)));
+                }
+                let path = self.job_file(name)?;
+                fs::copy(&file, &path)?;
+                if Path::new(name).extension()==extension {
+                    graders.push(path);
+                }
+            }
+            language.replace_with("%GRADER%", &graders);
+            // nothing to compile for an interpreted language
+            if language.command.is_empty() {
+                ret = true;
+                return Ok(RunResult::CompilationSuccess);
+            }
 
-            let mut process = Command::new(&language.command[0])
-                .args(&language.command[1..])
-                .spawn()?;
-            let exitstatus = process.wait()?;
-            if !exitstatus.success() { return Ok(RunResult::CompilationError);}
+            // write both stdout and stderr of the compiler to one file
+            let output = OpenOptions::new().read(true).write(true).truncate(true).create(true)
+                .open(self.path("compile_output"))?;
+            // the compiler and the processes it starts are in a new process group
+            // so that all of them can be killed
+            let mut command = Command::new(&language.command[0]);
+            command.args(&language.command[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::from(output.try_clone()?))
+                .stderr(Stdio::from(output));
+            process::set_process_group(&mut command);
+            process::set_memory_limit(&mut command, language.compile_memory_limit);
+            // the compiler runs in the sandbox like the program
+            // so that it can not read the hidden files into the program
+            // its temp files are written to the work dir, which is the only writable one
+            if config.sandbox.enabled {
+                command.env("TMPDIR", fs::canonicalize(self.work_dir())?);
+                sandbox::apply_to_compiler(&mut command, config, Path::new(&self.work_dir()))?;
+            }
+            let mut process = command.spawn()?;
+            let res = process::wait_with_usage(&mut process, Duration::from_micros(language.compile_time_limit));
+            process::kill_process_group(&process);
+            let res = res?;
+            if res.timed_out {
+                self.case_res[caseidx].info = "compile timeout".to_string();
+                return Ok(RunResult::CompilationError);
+            }
+            self.case_res[caseidx].info = read_truncated(&self.path("compile_output"), config.server.compile_output_limit)?;
+            let output_tail = read_tail(&self.path("compile_output"), LOG_TAIL)?;
+            if process::is_out_of_memory(&res, language.compile_memory_limit, &output_tail) {
+                self.case_res[caseidx].info.insert_str(0, "compile memory limit exceeded\n");
+            }
+            if !res.status.success() { return Ok(RunResult::CompilationError);}
             ret = true;
             return Ok(RunResult::CompilationSuccess);
         };
@@ -284,6 +753,80 @@ impl Job {
     }
 }
 
+// the time limit of the special judge or the interactor in microseconds
+fn judge_time_limit(problem: &config::Problem) -> u64 {
+    problem.misc.get("special_judge_time_limit")
+        .and_then(|limit| limit.as_u64())
+        .unwrap_or(SPJ_TIME_LIMIT)
+}
+
+// parse the optional line of the fraction of the case score
+// the line is returned as the error if it is not a number from 0 to 1
+fn parse_fraction(line: Option<&str>) -> Result<Option<f32>, String> {
+    match line.map(|line| { line.trim() }).filter(|line| { !line.is_empty() }) {
+        Some(line) => match line.parse::<f32>() {
+            Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(Some(fraction)),
+            _ => Err(line.to_string()),
+        },
+        None => Ok(None),
+    }
+}
+
+// read at most limit bytes of the file
+// a note is added if the file is longer
+fn read_truncated(path: &str, limit: usize) -> io::Result<String> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut out = Vec::new();
+    file.take(limit as u64).read_to_end(&mut out)?;
+    return Ok(excerpt(&out, size, limit));
+}
+
+// at most limit bytes from the beginning of an output of the size
+// a note is added if the output is longer
+fn excerpt(out: &[u8], size: u64, limit: usize) -> String {
+    let out = &out[..out.len().min(limit)];
+    let mut text = String::from_utf8_lossy(out).to_string();
+    if size <= out.len() as u64 {
+        return text;
+    }
+    // do not end with a broken character
+    if text.ends_with(char::REPLACEMENT_CHARACTER) {
+        text.pop();
+    }
+    text.push_str(&format!("\n... ({} bytes truncated)", size - out.len() as u64));
+    return text;
+}
+
+// read at most the last limit bytes of the file
+fn read_tail(path: &str, limit: usize) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(limit as u64)))?;
+    let mut out = Vec::new();
+    file.read_to_end(&mut out)?;
+    return Ok(out);
+}
+
+// compare the output with the answer
+// strict mode compares byte by byte
+// otherwise the trailing whitespace of each line and the trailing blank lines are ignored
+fn compare(ans: &str, out: &str, strict: bool) -> bool {
+    if strict {
+        return ans==out;
+    }
+    let trim = |text: &str| -> Vec<String> {
+        let mut lines: Vec<String> = text.lines().map(|line| {
+            line.trim_end().to_string()
+        }).collect();
+        while lines.last().map_or(false, |line| { line.is_empty() }) {
+            lines.pop();
+        }
+        lines
+    };
+    return trim(ans)==trim(out);
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -299,7 +842,8 @@ mod test {
             language: "Rust".to_string(),
             user_id: 0,
             contest_id: 0,
-            problem_id: 0
+            problem_id: 0,
+            archive: None
         };
         let mut job = Job::new("root", 0, &info);
         job.init(&config);
@@ -313,6 +857,94 @@ mod test {
         job.clear();
     }
     #[test]
+    fn test_compile_error() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        config.server.compile_output_limit = 64;
+        let info = JobInfo {
+            source_code: "fn main() { let x: i32 = \"one\"; }".to_string(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1002, &info);
+        job.init(&config);
+        assert!(!job.compile_source_code(&config, 0));
+        assert_eq!(job.case_res[0].result, RunResult::CompilationError);
+        assert!(job.case_res[0].info.starts_with("error[E0308]"));
+        assert!(job.case_res[0].info.ends_with("bytes truncated)"));
+
+        // the compiler is killed after the time limit
+        config.languages[0].command = vec!["sh".to_string(), "-c".to_string(), "sleep 10 & sleep 10".to_string()];
+        config.languages[0].compile_time_limit = 100_000;
+        assert!(!job.compile_source_code(&config, 0));
+        assert_eq!(job.case_res[0].result, RunResult::CompilationError);
+        assert_eq!(job.case_res[0].info, "compile timeout");
+        job.clear();
+    }
+    #[test]
+    fn test_compare() {
+        assert!(compare("1 2\n3\n", "1 2\n3\n", true));
+        assert!(!compare("1 2\n3\n", "1 2  \n3", true));
+        assert!(compare("1 2\n3\n", "1 2  \n3", false));
+        assert!(compare("1 2\n3\n", "1 2\r\n3\n\n\n", false));
+        assert!(!compare("1 2\n3\n", " 1 2\n3\n", false));
+        assert!(!compare("1 2\n3\n", "1 2\n\n3\n", false));
+    }
+    #[test]
+    fn test_special_judge() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let info = JobInfo {
+            source_code: String::new(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1000, &info);
+        job.init(&config);
+        let problem = &config.problems[0];
+        let case = &problem.cases[0];
+        let judge = |script: &str| {
+            job.special_judge(problem, &serde_json::json!(["sh", "-c", script]), case)
+        };
+        assert_eq!(judge("printf 'Accepted\\nok\\n'"), (RunResult::Accepted, "ok".to_string(), None));
+        assert_eq!(judge("printf 'Accepted\\nsuboptimal\\n0.6\\n'").2, Some(0.6));
+        assert_eq!(judge("printf 'Accepted\\nok\\n1.5\\n'").0, RunResult::SpjError);
+        assert_eq!(judge("printf 'Wrong Answer\\nbad\\n'").0, RunResult::WrongAnswer);
+        assert_eq!(judge("printf 'Wrong Answer\\nbad\\n0.5\\n'").0, RunResult::SpjError);
+        assert_eq!(judge("echo Maybe; echo why").0, RunResult::SpjError);
+        assert_eq!(judge("echo Accepted").0, RunResult::SpjError);
+        assert_eq!(judge("echo Accepted; echo ok; exit 1").0, RunResult::SpjError);
+        assert_eq!(judge("kill -9 $$").0, RunResult::SpjError);
+        job.clear();
+
+        // the fraction of each case is added to the score of the job
+        let mut config = config.clone();
+        let spj = |script: &str| { serde_json::json!({ "special_judge": ["sh", "-c", script] }) };
+        config.problems[0].misc = spj("printf 'Accepted\\nsuboptimal\\n0.6\\n'");
+        let info = JobInfo {
+            source_code: "print(sum(int(input()) for _ in range(2)))".to_string(),
+            language: "Python".to_string(),
+            ..info
+        };
+        let mut job = Job::new("root", 1000, &info);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+        assert!((job.score - 60.0).abs() < 1e-4);
+        // a wrong answer earns nothing
+        config.problems[0].misc = spj("printf 'Wrong Answer\\nbad\\n0.5\\n'");
+        job.reset(&config);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::SpjError);
+        assert_eq!(job.score, 0.0);
+        job.clear();
+    }
+    #[test]
     fn test_run_one_case() {
         let json = fs::read_to_string("./config.json").unwrap();
         let config: Config = serde_json::from_str(&json).expect("Parse failed");
@@ -321,7 +953,8 @@ mod test {
             language: "Rust".to_string(),
             user_id: 0,
             contest_id: 0,
-            problem_id: 0
+            problem_id: 0,
+            archive: None
         };
         let mut job = Job::new("root", 0, &info);
         job.init(&config);
@@ -331,14 +964,422 @@ mod test {
         let case1 = &problem.cases[0];
         let case2 = &problem.cases[1];
 
-        let res = job.run_one_case(problem, case1, 1);
+        let res = job.run_one_case(&config, problem, case1, 1);
         assert!(res);
-        let res = job.run_one_case(problem, case2, 2);
+        assert!(job.case_res[1].memory > 0);
+        assert_eq!(job.case_res[1].time_limit, case1.time_limit as u64);
+        let res = job.run_one_case(&config, problem, case2, 2);
         assert!(res);
 
         job.clear();
     }
     #[test]
+    fn test_sandbox() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        config.sandbox.enabled = true;
+        config.sandbox.hidden_paths = vec!["./config.json".to_string()];
+        // the program fails if it can escape the sandbox
+        let info = JobInfo {
+            source_code: r#"use std::io::Read;
+fn main() {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let sum: i32 = input.split_whitespace().map(|x| x.parse::<i32>().unwrap()).sum();
+    assert!(std::fs::write("./sandbox_escape", "x").is_err());
+    assert!(std::net::TcpStream::connect("127.0.0.1:12345").is_err());
+    assert!(!std::process::Command::new("unshare").args(["--user", "true"]).status().unwrap().success());
+    assert_eq!(std::fs::read_dir("./tmp").unwrap().count(), 1);
+    assert!(std::fs::read_to_string("./config.json").unwrap().is_empty());
+    assert!(std::fs::read_dir("./tests/data/aplusb").unwrap().next().is_none());
+    std::fs::write("./tmp/job_1001/scratch", "x").unwrap();
+    let t = std::thread::spawn(move || sum);
+    println!("{}", t.join().unwrap());
+}
+"#.to_string(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1001, &info);
+        job.init(&config);
+        assert!(job.compile_source_code(&config, 0));
+        let problem = &config.problems[0];
+        assert!(job.run_one_case(&config, problem, &problem.cases[0], 1));
+        assert_eq!(job.case_res[1].result, RunResult::Accepted);
+        assert!(Path::new(&job.path("scratch")).is_file());
+        assert!(!Path::new("./sandbox_escape").exists());
+
+        // the compiler can not read the hidden files either
+        // the hidden directory is empty and the hidden file is empty
+        let root = fs::canonicalize(".").unwrap();
+        job.info.source_code = format!("fn main() {{ print!(\"{{}}\", include_str!(\"{}\")); }}",
+            root.join("tests/data/aplusb/1.ans").display());
+        assert!(!job.compile_source_code(&config, 0));
+        job.info.source_code = format!("const _: () = assert!(include_str!(\"{}\").is_empty());\nfn main() {{}}",
+            root.join("config.json").display());
+        assert!(job.compile_source_code(&config, 0));
+        job.clear();
+    }
+    #[test]
+    fn test_output_limit() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        config.problems[0].cases[0].output_limit = 1024;
+        let info = JobInfo {
+            source_code: "fn main() { loop { println!(\"spam\"); } }".to_string(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1003, &info);
+        job.init(&config);
+        assert!(job.compile_source_code(&config, 0));
+        let problem = &config.problems[0];
+        job.run_one_case(&config, problem, &problem.cases[0], 1);
+        assert_eq!(job.case_res[1].result, RunResult::OutputLimitExceeded);
+        assert!(fs::metadata(job.path("output")).unwrap().len() <= 1024);
+
+        // the stderr has its own limit and does not exceed the output limit
+        config.server.stderr_limit = 100;
+        job.info.language = "Python".to_string();
+        job.info.source_code = "import sys\nsys.stderr.write('x' * 4000000)\nprint(sum(int(input()) for _ in range(2)))".to_string();
+        assert!(job.compile_source_code(&config, 0));
+        job.run_one_case(&config, problem, &problem.cases[0], 1);
+        assert_eq!(job.case_res[1].result, RunResult::Accepted);
+        assert_eq!(fs::metadata(job.path("case_1.stderr")).unwrap().len(), 100);
+        job.clear();
+    }
+    #[test]
+    fn test_escaped_process() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        // a process leaving the process group keeps the stdout and stderr of the program
+        let info = JobInfo {
+            source_code: "import subprocess\nsubprocess.Popen(['setsid', 'sleep', '20'])\nprint(sum(int(input()) for _ in range(2)))".to_string(),
+            language: "Python".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1011, &info);
+        let start = std::time::Instant::now();
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        job.clear();
+    }
+    #[test]
+    fn test_time_limit() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        config.problems[0].cases[0].time_limit = 200_000;
+        // sleeping does not use the cpu time
+        let info = JobInfo {
+            source_code: "import time\ntime.sleep(0.5)\nprint(sum(int(input()) for _ in range(2)))".to_string(),
+            language: "Python".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        config.languages[1].time_multiplier = 1.0;
+        config.languages[1].extra_time = 0;
+        let mut job = Job::new("root", 1005, &info);
+        job.init(&config);
+        assert!(job.compile_source_code(&config, 0));
+        let problem = &config.problems[0];
+        job.run_one_case(&config, problem, &problem.cases[0], 1);
+        assert_eq!(job.case_res[1].result, RunResult::Accepted);
+        assert!(job.case_res[1].time >= 500_000);
+        assert_eq!(job.case_res[1].time_limit, 200_000);
+        assert_eq!(job.case_res[1].wall_time_limit, 2_000_000);
+
+        // a busy loop is stopped by the cpu time limit
+        job.info.source_code = "while True: pass".to_string();
+        assert!(job.compile_source_code(&config, 0));
+        job.run_one_case(&config, problem, &problem.cases[0], 1);
+        assert_eq!(job.case_res[1].result, RunResult::TimeLimitExceeded);
+        // a busy machine may reach the wall clock time limit first
+        let info = job.case_res[1].info.as_str();
+        assert!(info == "SIGXCPU" || info == "wall clock time limit exceeded", "{}", info);
+        assert!(job.case_res[1].cpu_time > 200_000);
+
+        // the exit code of a runtime error is shown
+        job.info.source_code = "raise SystemExit(101)".to_string();
+        assert!(job.compile_source_code(&config, 0));
+        job.run_one_case(&config, problem, &problem.cases[0], 1);
+        assert_eq!(job.case_res[1].result, RunResult::RuntimeError);
+        assert_eq!(job.case_res[1].info, "exit code 101 (panic)");
+
+        // the stderr is saved and shown in the info if enabled
+        config.server.show_stderr = true;
+        job.info.source_code = "import sys\nsys.exit('bad input')".to_string();
+        assert!(job.compile_source_code(&config, 0));
+        job.run_one_case(&config, problem, &problem.cases[0], 1);
+        assert_eq!(job.case_res[1].info, "exit code 1\nbad input\n");
+        assert_eq!(fs::read_to_string(job.path("case_1.stderr")).unwrap(), "bad input\n");
+        job.clear();
+    }
+    #[test]
+    fn test_interpreted() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        // first without the sandbox
+        config.sandbox.enabled = false;
+        let info = JobInfo {
+            source_code: "print(sum(int(input()) for _ in range(2)))".to_string(),
+            language: "Python".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1004, &info);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        // the interpreter works in the sandbox
+        config.sandbox.enabled = true;
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        // only a failed allocation is a memory limit, not any abort
+        job.info.source_code = "import os\nos.abort()".to_string();
+        job.run(&config);
+        assert_eq!(job.result, RunResult::RuntimeError);
+        job.info.source_code = "x = bytearray(1 << 40)".to_string();
+        job.run(&config);
+        assert_eq!(job.result, RunResult::MemoryLimitExceeded);
+
+        // a shell script starting other programs
+        config.languages[1].file_name = "main.sh".to_string();
+        config.languages[1].run = vec!["sh".to_string(), "%WORKDIR%/main.sh".to_string()];
+        job.info.source_code = "read a; read b; expr $a + $b".to_string();
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+        job.clear();
+    }
+    #[test]
+    fn test_interactive() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        // first without the sandbox
+        config.sandbox.enabled = false;
+        config.problems[0].problem_type = config::ProblemType::interactive;
+        config.problems[0].cases[0].time_limit = 200_000;
+        config.languages[1].time_multiplier = 1.0;
+        config.languages[1].extra_time = 0;
+        // the interactor sends the input and checks the reply of the program
+        let interactor = |script: &str| {
+            serde_json::json!(["sh", "-c", format!("cat \"$0\"; read x; {}", script), "%INPUT%", "%ANSWER%"])
+        };
+        let check = "if [ \"$x\" = \"$(cat \"$1\")\" ]; then echo ok >&2; else echo \"read $x\" >&2; exit 1; fi";
+        config.problems[0].misc = serde_json::json!({ "interactor": interactor(check) });
+        let info = JobInfo {
+            source_code: "print(sum(int(input()) for _ in range(2)))".to_string(),
+            language: "Python".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1006, &info);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+        assert_eq!(job.case_res[1].info, "ok");
+
+        // the interactor works with the sandboxed program
+        config.sandbox.enabled = true;
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        job.info.source_code = "print(sum(int(input()) for _ in range(2)) + 1)".to_string();
+        job.run(&config);
+        assert_eq!(job.case_res[1].result, RunResult::WrongAnswer);
+        assert_eq!(job.case_res[1].info, "read 9596");
+
+        // the program waiting for more input is stopped by the time limit
+        job.info.source_code = "input()\ninput()\ninput()".to_string();
+        job.run(&config);
+        assert_eq!(job.case_res[1].result, RunResult::TimeLimitExceeded);
+
+        // a fraction of the score and a broken interactor
+        job.info.source_code = "print(sum(int(input()) for _ in range(2)))".to_string();
+        config.problems[0].misc = serde_json::json!({ "interactor": interactor("printf 'ok\\n0.5\\n' >&2") });
+        job.run(&config);
+        assert_eq!(job.case_res[1].result, RunResult::Accepted);
+        assert_eq!(job.case_res[1].score, 25.0);
+        config.problems[0].misc = serde_json::json!({ "interactor": interactor("exit 3") });
+        job.run(&config);
+        assert_eq!(job.case_res[1].result, RunResult::SpjError);
+        config.problems[0].misc = serde_json::json!({});
+        job.run(&config);
+        assert_eq!(job.case_res[1].result, RunResult::SystemError);
+        job.clear();
+    }
+    #[test]
+    fn test_file_io() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        // first without the sandbox
+        config.sandbox.enabled = false;
+        config.problems[0].misc = serde_json::json!({ "input_file": "input.txt", "output_file": "output.txt" });
+        let info = JobInfo {
+            source_code: "a, b = open('input.txt').read().split()\nopen('output.txt', 'w').write(str(int(a) + int(b)))".to_string(),
+            language: "Python".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1007, &info);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        // the program writes in its work dir in the sandbox
+        config.sandbox.enabled = true;
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        // the stdout is not judged
+        job.info.source_code = "a, b = open('input.txt').read().split()\nprint(int(a) + int(b))".to_string();
+        job.run(&config);
+        assert_eq!(job.result, RunResult::WrongAnswer);
+
+        // the files must be in the work dir
+        config.problems[0].misc = serde_json::json!({ "input_file": "../input.txt" });
+        job.run(&config);
+        assert_eq!(job.result, RunResult::SystemError);
+        job.clear();
+    }
+    #[test]
+    fn test_grader() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        config.problems[0].misc = serde_json::json!({ "graders": {
+            "Rust": ["./tests/data/grader/grader.rs"],
+            "C++": ["./tests/data/grader/grader.cpp", "./tests/data/grader/solve.h"]
+        }});
+        // the grader is the root of the crate and includes the submission
+        config.languages[0].command = ["rustc", "-o", "%OUTPUT%", "%GRADER%"].map(String::from).to_vec();
+        let mut cpp = config.languages[0].clone();
+        cpp.name = "C++".to_string();
+        cpp.file_name = "main.cpp".to_string();
+        cpp.command = ["g++", "-o", "%OUTPUT%", "%INPUT%", "%GRADER%"].map(String::from).to_vec();
+        config.languages.push(cpp);
+        let info = JobInfo {
+            source_code: "fn solve(a: i64, b: i64) -> i64 { a + b }".to_string(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new("root", 1008, &info);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        // the header is copied but not compiled
+        job.info.language = "C++".to_string();
+        job.info.source_code = "#include \"solve.h\"\nlong long solve(long long a, long long b) { return a + b; }".to_string();
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+        assert!(fs::metadata(job.path("solve.h")).is_ok());
+
+        // without the grader there is no main
+        config.problems[0].misc = serde_json::json!({});
+        job.run(&config);
+        assert_eq!(job.result, RunResult::CompilationError);
+        job.clear();
+    }
+    #[test]
+    fn test_archive() {
+        use base64::Engine;
+        let json = fs::read_to_string("./config.json").unwrap();
+        let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let mut make = config.languages[0].clone();
+        make.name = "Make".to_string();
+        make.file_name = "Makefile".to_string();
+        make.command = ["make", "-s", "-C", "%WORKDIR%"].map(String::from).to_vec();
+        make.run = vec!["%WORKDIR%/main".to_string()];
+        config.languages.push(make);
+        config.sandbox.hidden_paths = vec!["./config.json".to_string()];
+        // the build runs in the sandbox, it fails if the makefile can escape
+        let makefile = "main: main.cpp src/add.cpp\n\
+            \t! touch ../escape\n\
+            \t! cat ../../tests/data/aplusb/1.ans\n\
+            \ttest ! -s ../../config.json\n\
+            \tg++ -o main main.cpp src/add.cpp\n";
+        let files: [(&str, &str); 3] = [
+            ("Makefile", makefile),
+            ("main.cpp", "#include <iostream>\nlong long add(long long a, long long b);\nint main() { long long a, b; std::cin >> a >> b; std::cout << add(a, b) << std::endl; }\n"),
+            ("src/add.cpp", "long long add(long long a, long long b) { return a + b; }\n"),
+        ];
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        let archive = base64::engine::general_purpose::STANDARD.encode(builder.into_inner().unwrap());
+        let info = JobInfo {
+            source_code: String::new(),
+            language: "Make".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: Some(archive)
+        };
+        let mut job = Job::new("root", 1009, &info);
+        job.run(&config);
+        assert_eq!(job.result, RunResult::Accepted);
+
+        // a broken archive is a compilation error
+        job.info.archive = Some("bm90IGFuIGFyY2hpdmU=".to_string());
+        job.run(&config);
+        assert_eq!(job.result, RunResult::CompilationError);
+        assert!(job.case_res[0].info.starts_with("invalid archive"));
+        job.clear();
+    }
+    #[test]
+    fn test_run_custom() {
+        let json = fs::read_to_string("./config.json").unwrap();
+        let config: Config = serde_json::from_str(&json).expect("Parse failed");
+        let info = JobInfo {
+            source_code: "import sys\nprint(input().upper())\nprint('debug', file=sys.stderr)".to_string(),
+            language: "Python".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let mut job = Job::new_custom_run(1010, &info);
+        let output = job.run_custom(&config, "hello\n");
+        assert_eq!(output.result, RunResult::Accepted);
+        assert_eq!(output.stdout, "HELLO\n");
+        assert_eq!(output.stderr, "debug\n");
+        assert!(output.memory > 0);
+        assert_eq!(output.time_limit, RUN_TIME_LIMIT as u64 * 3 + 100_000);
+        // the directory is removed and the jobs are not touched
+        assert!(!Path::new("./tmp/run_1010").exists());
+
+        let output = job.run_custom(&config, "");
+        assert_eq!(output.result, RunResult::RuntimeError);
+        assert_eq!(output.info, "exit code 1");
+
+        job.info.language = "Rust".to_string();
+        let output = job.run_custom(&config, "");
+        assert_eq!(output.result, RunResult::CompilationError);
+        assert!(!output.info.is_empty());
+    }
+    #[test]
     fn test_run_aplusb() {
         let json = fs::read_to_string("./config.json").unwrap();
         let config: Config = serde_json::from_str(&json).expect("Parse failed");
@@ -347,7 +1388,8 @@ mod test {
             language: "Rust".to_string(),
             user_id: 0,
             contest_id: 0,
-            problem_id: 0
+            problem_id: 0,
+            archive: None
         };
         let mut job = Job::new("root", 0, &info);
         // job.init();
@@ -365,7 +1407,8 @@ mod test {
             language: "Rust".to_string(),
             user_id: 0,
             contest_id: 0,
-            problem_id: 0
+            problem_id: 0,
+            archive: None
         };
         // job.init();
         let mut job = Job::new("root", 0, &info);
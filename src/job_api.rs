@@ -84,7 +84,7 @@ pub async fn get_jobs(query: web::Query<UrlQuery>) -> impl Responder {
     let query = query.into_inner();
     let job_data = JOBDATA.clone();
     let job_data_inner = job_data.lock().unwrap();
-    let mut temp_job_list: Vec<&Job> = job_data_inner.job_list.iter().collect();
+    let mut temp_job_list: Vec<&Job> = job_data_inner.storage().job_list();
     temp_job_list.sort_by_key(|x| {x.created_time});
 
     let res: Vec<Response> = temp_job_list.iter().filter(|job| {
@@ -107,19 +107,39 @@ pub async fn get_jobs_id(jobid: web::Path<u32>) -> Result<HttpResponse, AppError
     return Ok(HttpResponse::Ok().json(response));
 }
 
+// the output of the compiler of a job
+#[derive(Debug, Serialize)]
+struct CompileOutput {
+    id: u32,
+    result: RunResult,
+    output: String
+}
+
+// get the compiler output of the job with the id
+#[get("/jobs/{jobid}/compile_output")]
+pub async fn get_compile_output(jobid: web::Path<u32>) -> Result<HttpResponse, AppError> {
+    let job_data = JOBDATA.clone();
+    let job_data_inner = job_data.lock().unwrap();
+    let job = job_data_inner.storage().find_job(*jobid)?;
+    let res = CompileOutput {
+        id: job.job_id,
+        result: job.case_res[0].result,
+        output: job.case_res[0].info.clone()
+    };
+    log::info!(target: "get_compile_output", "get compile output of job {}", res.id);
+    return Ok(HttpResponse::Ok().json(res));
+}
+
 // rerun the job of the id
+// the job is put back to the judge queue
 #[put("/jobs/{jobid}")]
 pub async fn put_job(jobid: web::Path<u32>, config: web::Data<Config>) -> Result<HttpResponse, AppError> {
     let job_data = JOBDATA.clone();
     let mut job_data_inner = job_data.lock().unwrap();
-    let job = job_data_inner.find_job_mut(*jobid)?;
-    if job.state != State::Finished {
-        return Err(AppError::ERR_INVALID_STATE);
-    }
-    job.run(&config);
+    let res = job_data_inner.rejudge_job(*jobid, &config)?;
 
-    log::info!(target: "put_job", "put jobs {}", job.job_id);
-    return Ok(HttpResponse::Ok().json(job.response()));
+    log::info!(target: "put_job", "put jobs {}", res.id);
+    return Ok(HttpResponse::Ok().json(res));
 }
 
 // delete the job
@@ -127,18 +147,7 @@ pub async fn put_job(jobid: web::Path<u32>, config: web::Data<Config>) -> Result
 pub async fn delete_job(jobid: web::Path<u32>) -> Result<HttpResponse, AppError> {
     let job_data = JOBDATA.clone();
     let mut job_data_inner = job_data.lock().unwrap();
-    let job = job_data_inner.job_list.iter_mut().enumerate().find(|x| {
-        x.1.job_id==*jobid
-    });
-    if job.is_none() {
-        return Err(AppError::ERR_NOT_FOUND);
-    }
-    let (idx, job) = job.unwrap();
-    if job.state != State::Queueing {
-        return Err(AppError::ERR_INVALID_STATE);
-    }
-    log::info!(target: "delete_job", "delete job {}", job.job_id);
-    job_data_inner.job_list.remove(idx);
+    job_data_inner.delete_job(*jobid)?;
+    log::info!(target: "delete_job", "delete job {}", jobid);
     return Ok(HttpResponse::Ok().finish());
 }
-
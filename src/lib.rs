@@ -1,8 +1,16 @@
 
+#![allow(clippy::needless_return)]
+
 pub mod config;
 pub mod job;
+pub mod worker;
+pub mod storage;
+pub mod process;
+pub mod sandbox;
+pub mod archive;
 
 pub mod job_api;
+pub mod run_api;
 pub mod user_api;
 pub mod contest_api;
 
@@ -19,6 +27,7 @@ use derive_more::{Display, Error};
 use user_api::UserInfo;
 use job::{JobInfo, Job};
 use contest_api::{ContestInfo, HttpcomInfo};
+use storage::{Storage, MemoryStorage};
 
 
 
@@ -76,26 +85,37 @@ impl ResponseError for AppError {
 
 
 
-// the jobdata has a job list, user list and a contest list
+// a contest with the submission times of each (user_id, problem_id)
+pub type ContestEntry = (ContestInfo, HashMap<(u32, u32), u32>);
+
+// the jobdata holds the storage of the jobs, users and contests
+// and checks the requests before changing the storage
 pub struct JobData {
-    job_list: Vec<job::Job>,
-    total_jobs: u32,
-    user_list: Vec<User>,
-    total_users: u32,
-    contests_list: Vec<(ContestInfo, HashMap<(u32, u32), u32>)>,
-    total_contests: u32,
+    storage: Box<dyn Storage>,
 }
 
 impl JobData {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        Self { storage }
+    }
+    // read only access to the storage
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+    // direct access to the storage, the caller should check the change
+    pub fn storage_mut(&mut self) -> &mut dyn Storage {
+        self.storage.as_mut()
+    }
     // add job to the job list
     // first check if it is valid
-    // if valid add it to the list and return the run response
+    // if valid add it to the list and the judge queue
+    // and return the queueing response
     // otherwise return error
     pub fn add_job(&mut self, info: &JobInfo, config: &config::Config) -> Result<Response, AppError> {
-        let id = self.total_jobs;
+        let id = self.storage.next_job_id();
 
         // check the user id 
-        let res = self.find_user(info.user_id)?;
+        let res = self.storage.find_user(info.user_id)?;
 
         let user_name = res.name.clone();
         let mut job = Job::new(&user_name, id, info);
@@ -104,123 +124,89 @@ impl JobData {
         if !job.is_valid(config) {
             return Err(AppError::ERR_NOT_FOUND);
         }
+        // check the encoding and the size of the archive
+        if let Some(archive) = &info.archive {
+            archive::decode(archive, &config.server).map_err(|_| AppError::ERR_INVALID_ARGUMENT)?;
+        }
 
-        let mut temp = 0;
-        let mut submission_time = &mut temp;
+        let mut contest = None;
         // contest check
         if info.contest_id != 0 {
             // check contest id
-            let contest = self.find_contest_mut(info.contest_id)?;
-            if !contest.0.is_valid(&info) {  // check user id and problem id in the contest
+            let mut temp = self.storage.find_contest(info.contest_id)?.clone();
+            if !temp.0.is_valid(info) {  // check user id and problem id in the contest
                 return Err(AppError::ERR_INVALID_ARGUMENT);
             }
             // check submission_time
-            let entry = contest.1.entry((info.user_id, info.problem_id)).or_insert(0);
-            if *entry >= contest.0.submission_limit {
+            let entry = temp.1.entry((info.user_id, info.problem_id)).or_insert(0);
+            if *entry >= temp.0.submission_limit {
                 return Err(AppError::ERR_RATE_LIMIT);
             }
-            submission_time = entry;
+            *entry += 1;
+            contest = Some(temp);
         }
-        // run the job and get the response
-        job.run(config);
+        // the job will be run by a judge worker
+        job.reset(config);
         let res = job.response();
 
-        *submission_time += 1;
-        self.total_jobs += 1;
-        self.job_list.push(job);
+        // the job and the submission count are stored together
+        // so a failure does not leave a job which is never judged
+        self.storage.submit_job(job, contest)?;
+        worker::push_job(id);
         Ok(res)
     }
-    // find the contest
-    pub fn find_contest(&self, contest_id: u32) -> Result<&(ContestInfo, HashMap<(u32,u32), u32>), AppError> {
-        let response = self.contests_list.iter().find(|x| {
-            x.0.id==contest_id
-        });
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x) }
-            );
-    }
-    // find the contest
-    pub fn find_contest_mut(&mut self, contest_id: u32) -> Result<&mut (ContestInfo, HashMap<(u32,u32), u32>), AppError> {
-        let response = self.contests_list.iter_mut().find(|x| {
-            x.0.id==contest_id
-        });
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x) }
-            );
-    }
-    // find the user
-    pub fn find_user_mut(&mut self, user_id: u32) -> Result<&mut User, AppError> {
-        let response = self.user_list.iter_mut().find(|x| {
-            x.id==user_id
-        });
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x) }
-            );
-    }
-    // find the user
-    pub fn find_user(&self, user_id: u32) -> Result<&User, AppError> {
-        let response = self.user_list.iter().find(|x| {
-            x.id==user_id
-        });
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x) }
-            );
-    }
-    // find the job
-    pub fn find_job_mut(&mut self, jobid: u32) -> Result<&mut Job, AppError> {
-        let response = self.job_list.iter_mut().find(|x| {
-            x.job_id==jobid
-        });
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x) }
-            );
-    }
-    // find the job
-    pub fn find_job(&self, jobid: u32) -> Result<&Job, AppError> {
-        let response = self.job_list.iter().find(|x| {
-            x.job_id==jobid
-        });
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x) }
-            );
-    }
     // get the job response
     pub fn get_job_response(&self, jobid: u32) -> Result<Response, AppError> {
-        let response = self.find_job(jobid);
-        return response.map_or(
-            Err(AppError::ERR_NOT_FOUND),
-            |x| { Ok(x.response()) }
-            );
+        let response = self.storage.find_job(jobid)?;
+        return Ok(response.response());
+    }
+    // put a finished job back to the judge queue
+    pub fn rejudge_job(&mut self, jobid: u32, config: &config::Config) -> Result<Response, AppError> {
+        let mut job = self.storage.find_job(jobid)?.clone();
+        if job.state != State::Finished {
+            return Err(AppError::ERR_INVALID_STATE);
+        }
+        // the problem or the language may have been removed from the config
+        if !job.is_valid(config) {
+            return Err(AppError::ERR_NOT_FOUND);
+        }
+        job.reset(config);
+        let res = job.response();
+        self.storage.update_job(job)?;
+        worker::push_job(jobid);
+        return Ok(res);
+    }
+    // delete a job which is still in the queue
+    pub fn delete_job(&mut self, jobid: u32) -> Result<(), AppError> {
+        let job = self.storage.find_job(jobid)?;
+        if job.state != State::Queueing {
+            return Err(AppError::ERR_INVALID_STATE);
+        }
+        self.storage.remove_job(jobid)?;
+        return Ok(());
     }
     // post a user 
     pub fn post_user(&mut self, mut info: UserInfo) -> Result<User, AppError> {
         // check valid
         if let Some(id) = info.id {
-            self.find_user(id)?;
+            self.storage.find_user(id)?;
         }
-        if self.user_list.iter().any(|x| {
+        if self.storage.user_list().iter().any(|x| {
             x.name == info.name
         }) { return Err(AppError::ERR_INVALID_ARGUMENT);}
         // add the user
         match info.id {
             // update user
-            Some(id) => {
-                let user = self.find_user_mut(id)?;
-                *user = User::from(info);
-                Ok(user.clone())
+            Some(_) => {
+                let user = User::from(info);
+                self.storage.update_user(user.clone())?;
+                Ok(user)
             },
             // new user
             None => {
-                info.id = Some(self.total_users);
+                info.id = Some(self.storage.next_user_id());
                 let temp = User::from(info);
-                self.user_list.push(temp.clone());
-                self.total_users += 1;
+                self.storage.insert_user(temp.clone())?;
                 Ok(temp)
             },
         }
@@ -228,10 +214,10 @@ impl JobData {
     pub fn post_contest(&mut self, mut info: HttpcomInfo, config: &config::Config) -> Result<ContestInfo, AppError> {
         // check valid
         if let Some(id) = info.id {
-            self.find_contest(id)?;
+            self.storage.find_contest(id)?;
         }
         let res = info.user_ids.iter().all(|x| {
-            self.user_list.iter().any(|user| {user.id==*x})
+            self.storage.find_user(*x).is_ok()
         });
         if !res {
             return Err(AppError::ERR_NOT_FOUND);
@@ -248,16 +234,15 @@ impl JobData {
             // update contest
             Some(id) => {
                 let contest = ContestInfo::from(info);
-                let pos = self.find_contest_mut(id)?;
-                pos.0 = contest.clone();
+                let times = self.storage.find_contest(id)?.1.clone();
+                self.storage.update_contest((contest.clone(), times))?;
                 return Ok(contest);
             },
             // new contest
             None => {
-                info.id = Some(self.total_contests);
-                self.total_contests += 1;
+                info.id = Some(self.storage.next_contest_id());
                 let contest = ContestInfo::from(info);
-                self.contests_list.push((contest.clone(), HashMap::new()));
+                self.storage.insert_contest((contest.clone(), HashMap::new()))?;
                 return Ok(contest);
             },
         }
@@ -266,20 +251,7 @@ impl JobData {
 
 impl Default for JobData{
     fn default() -> Self {
-        let job_list: Vec<job::Job> = Vec::new();
-        let total_jobs = 0;
-        let user_list = vec![User{
-            id: 0,
-            name: "root".to_string()
-        }];
-        Self {
-            job_list, 
-            total_jobs,
-            user_list,
-            total_users: 1,
-            contests_list: Vec::new(),
-            total_contests: 1
-        }
+        Self::new(Box::<MemoryStorage>::default())
     }
 }
 
@@ -299,12 +271,28 @@ impl User {
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+// time is the wall clock time in microseconds
+// cpu_time is the user and system time in microseconds
+// memory is the peak resident set size in bytes
+// score is the score earned by the case
+// time_limit, wall_time_limit and memory_limit are the limits applied to the case
+// after the adjustment of the language
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CaseResult {
     id: u32,
     result: RunResult,
+    #[serde(default)]
+    score: f32,
     time: u32,
+    #[serde(default)]
+    cpu_time: u32,
     memory: u32,
+    #[serde(default)]
+    time_limit: u64,
+    #[serde(default)]
+    wall_time_limit: u64,
+    #[serde(default)]
+    memory_limit: u64,
     info: String
 }
 
@@ -313,8 +301,13 @@ impl CaseResult {
         Self {
             id,
             result: RunResult::Waiting,
+            score: 0.0,
             time: 0,
+            cpu_time: 0,
             memory: 0,
+            time_limit: 0,
+            wall_time_limit: 0,
+            memory_limit: 0,
             info: String::new()
         }
     }
@@ -379,6 +372,8 @@ pub enum RunResult {
     TimeLimitExceeded,
     #[serde(rename(serialize = "Memory Limit Exceeded", deserialize = "Memory Limit Exceeded"))]
     MemoryLimitExceeded,
+    #[serde(rename(serialize = "Output Limit Exceeded", deserialize = "Output Limit Exceeded"))]
+    OutputLimitExceeded,
     #[serde(rename(serialize = "System Error", deserialize = "System Error"))]
     SystemError,
     #[serde(rename(serialize = "SPJ Error", deserialize = "SPJ Error"))]
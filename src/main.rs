@@ -1,17 +1,24 @@
+#![allow(clippy::needless_return, clippy::single_component_path_imports, clippy::useless_format)]
+
 use std::fs;
+use std::time::Duration;
 
-use actix_web::{get, middleware::Logger, post, web, App, HttpServer, Responder};
+use actix_web::{get, middleware::Logger, post, web, App, HttpServer, Responder, http::KeepAlive};
 use env_logger;
 use log;
 
 use serde_json;
-use clap::{Command, arg};
+use clap::{Command, arg, value_parser};
 
 
 use oj::job_api;
+use oj::run_api;
 use oj::user_api;
 use oj::contest_api;
+use oj::worker;
 use oj::config::Config;
+use oj::{JOBDATA, JobData};
+use oj::storage::FileStorage;
 
 #[get("/hello/{name}")]
 async fn greet(name: web::Path<String>) -> impl Responder {
@@ -36,7 +43,19 @@ async fn main() -> std::io::Result<()> {
     let cmd = Command::new("cmd")
         .args(&[
             arg!(-c --config <CONFIG> "Specify a config file").required(false),
-            arg!(-f --"flush-data" "Fluash data").required(false)
+            arg!(-f --"flush-data" "Fluash data").required(false),
+            // override the server section of the config file
+            arg!(--"bind-address" <ADDRESS> "Override the bind address").required(false),
+            arg!(--"bind-port" <PORT> "Override the bind port").required(false)
+                .value_parser(value_parser!(u16)),
+            arg!(--workers <NUM> "Override the number of http workers").required(false)
+                .value_parser(value_parser!(usize)),
+            arg!(--"max-body-size" <BYTES> "Override the max size of a request body").required(false)
+                .value_parser(value_parser!(usize)),
+            arg!(--"keep-alive" <SECONDS> "Override the keep-alive timeout, 0 to disable").required(false)
+                .value_parser(value_parser!(u64)),
+            arg!(--"judge-workers" <NUM> "Override the number of judge workers").required(false)
+                .value_parser(value_parser!(usize))
         ]);
     let args = cmd.get_matches();
     let mut file_path = "./config.json";
@@ -44,11 +63,47 @@ async fn main() -> std::io::Result<()> {
         file_path = args.get_one::<String>("config").unwrap();
     }
     let json = fs::read_to_string(file_path).unwrap();
-    let config: Config = serde_json::from_str(&json).expect("Parse failed");
+    let mut config: Config = serde_json::from_str(&json).expect("Parse failed");
+    // the judged programs can not read the config file in the sandbox
+    config.sandbox.hidden_paths.push(file_path.to_string());
+
+    // the command line arguments take precedence over the config file
+    if let Some(address) = args.get_one::<String>("bind-address") {
+        config.server.bind_address = address.clone();
+    }
+    if let Some(port) = args.get_one::<u16>("bind-port") {
+        config.server.bind_port = *port;
+    }
+    if let Some(workers) = args.get_one::<usize>("workers") {
+        config.server.workers = Some(*workers);
+    }
+    if let Some(size) = args.get_one::<usize>("max-body-size") {
+        config.server.max_body_size = *size;
+    }
+    if let Some(seconds) = args.get_one::<u64>("keep-alive") {
+        config.server.keep_alive = *seconds;
+    }
+    if let Some(workers) = args.get_one::<usize>("judge-workers") {
+        config.server.judge_workers = *workers;
+    }
+    let server = config.server.clone();
+
+    // load the data saved by the last run
+    // or remove it if the flush-data flag is set
+    if args.contains_id("flush-data") {
+        FileStorage::flush(&server.data_file).expect("Flush data failed");
+    }
+    let storage = FileStorage::open(&server.data_file).expect("Load data failed");
+    *JOBDATA.lock().unwrap() = JobData::new(Box::new(storage));
+
+    // start the judge workers before accepting requests
+    worker::start_workers(&config);
+    worker::requeue_unfinished(&config);
 
-    HttpServer::new( move || {
+    let http_server = HttpServer::new( move || {
         App::new()
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::JsonConfig::default().limit(config.server.max_body_size))
             .wrap(Logger::default())
             .route("/hello", web::get().to(|| async { "Hello World!" }))
             .service(greet)
@@ -58,16 +113,28 @@ async fn main() -> std::io::Result<()> {
             .service(job_api::post_jobs)
             .service(job_api::get_jobs)
             .service(job_api::get_jobs_id)
+            .service(job_api::get_compile_output)
             .service(job_api::put_job)
             .service(job_api::delete_job)
+            .service(run_api::post_runs)
             .service(user_api::post_users)
             .service(user_api::get_users)
             .service(contest_api::post_contests)
             .service(contest_api::get_contests)
             .service(contest_api::get_contest_id)
             .service(contest_api::get_contest_ranklist)
-    })
-    .bind(("127.0.0.1", 12345))?
-    .run()
-    .await
+    });
+    let keep_alive = match server.keep_alive {
+        0 => KeepAlive::Disabled,
+        seconds => KeepAlive::Timeout(Duration::from_secs(seconds))
+    };
+    let http_server = http_server.keep_alive(keep_alive);
+    let http_server = match server.workers {
+        Some(workers) => http_server.workers(workers),
+        None => http_server
+    };
+    http_server
+        .bind((server.bind_address.as_str(), server.bind_port))?
+        .run()
+        .await
 }
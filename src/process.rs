@@ -0,0 +1,349 @@
+use std::io::{self, Read};
+use std::os::raw::c_int;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+// the resource usage of a finished process
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    // wall clock time since the process is waited
+    pub wall_time: Duration,
+    // user and system cpu time
+    pub cpu_time: Duration,
+    // peak resident set size in bytes
+    pub memory: u64
+}
+
+// the result of waiting a process with a time limit
+#[derive(Debug, Clone, Copy)]
+pub struct WaitResult {
+    pub status: ExitStatus,
+    // the process is killed because of the time limit
+    pub timed_out: bool,
+    pub usage: Usage
+}
+
+// wait the child process and collect its resource usage with wait4
+// kill the process if it runs longer than the timeout
+// the process is always reaped when this function returns Ok
+pub fn wait_with_usage(child: &mut Child, timeout: Duration) -> io::Result<WaitResult> {
+    let start = Instant::now();
+    let pid = child.id() as libc::pid_t;
+    let mut timed_out = false;
+    loop {
+        let mut status: c_int = 0;
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        let options = if timed_out { 0 } else { libc::WNOHANG };
+        let ret = unsafe { libc::wait4(pid, &mut status, options, &mut rusage) };
+        if ret == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        // the process has exited
+        if ret == pid {
+            return Ok(WaitResult {
+                status: ExitStatus::from_raw(status),
+                timed_out,
+                usage: Usage {
+                    wall_time: start.elapsed(),
+                    cpu_time: to_duration(rusage.ru_utime) + to_duration(rusage.ru_stime),
+                    memory: rusage.ru_maxrss as u64 * 1024
+                }
+            });
+        }
+        // still running
+        if start.elapsed() > timeout {
+            child.kill()?;
+            timed_out = true;
+        } else {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+// the output of a pipe read while the process runs
+// only the beginning and the end of the output are kept
+#[derive(Debug, Default)]
+pub struct Captured {
+    pub head: Vec<u8>,
+    pub tail: Vec<u8>,
+    // the size of the whole output in bytes
+    pub size: u64
+}
+
+impl Captured {
+    fn push(&mut self, data: &[u8], limit: usize, tail_limit: usize) {
+        self.size += data.len() as u64;
+        let len = limit.saturating_sub(self.head.len()).min(data.len());
+        self.head.extend_from_slice(&data[..len]);
+        self.tail.extend_from_slice(data);
+        if self.tail.len() > tail_limit {
+            self.tail.drain(..self.tail.len() - tail_limit);
+        }
+    }
+}
+
+// a thread reading a pipe, see capture
+pub struct Capture {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<Captured>
+}
+
+// read the pipe in a thread so that the writer is never blocked
+// the first limit bytes and the last tail_limit bytes are kept
+pub fn capture<R: Read + AsRawFd + Send + 'static>(mut pipe: R, limit: usize, tail_limit: usize) -> Capture {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let handle = thread::spawn(move || {
+        let mut captured = Captured::default();
+        let mut buf = [0u8; 8192];
+        // a process writing without end can not keep the thread after finish
+        let mut reads_after_stop = 64;
+        loop {
+            let stop = stopped.load(Ordering::SeqCst);
+            if stop {
+                if reads_after_stop == 0 {
+                    break;
+                }
+                reads_after_stop -= 1;
+            }
+            let mut fd = libc::pollfd { fd: pipe.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            let ret = unsafe { libc::poll(&mut fd, 1, if stop { 0 } else { 10 }) };
+            if ret < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            if ret < 0 || (ret == 0 && stop) {
+                break;
+            }
+            if ret == 0 {
+                continue;
+            }
+            match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => captured.push(&buf[..len], limit, tail_limit),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        captured
+    });
+    Capture { stop, handle }
+}
+
+impl Capture {
+    // stop reading and get the output
+    // the data left in the pipe is still read
+    // but a process escaping the process group and keeping the pipe open is not waited
+    pub fn finish(self) -> Captured {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().unwrap_or_default()
+    }
+}
+
+// limit the address space of the process started by the command
+// 0 means no limit
+pub fn set_memory_limit(command: &mut Command, bytes: u64) {
+    set_resource_limit(command, libc::RLIMIT_AS, bytes, bytes);
+}
+
+// limit the size of the files written by the process started by the command
+// the process gets SIGXFSZ when it writes over the limit
+// 0 means no limit
+pub fn set_output_limit(command: &mut Command, bytes: u64) {
+    set_resource_limit(command, libc::RLIMIT_FSIZE, bytes, bytes);
+}
+
+// limit the cpu time of the process started by the command
+// the limit is rounded up to seconds
+// the process gets SIGXCPU at the limit and SIGKILL one second later
+// 0 means no limit
+pub fn set_cpu_time_limit(command: &mut Command, time: Duration) {
+    let mut secs = time.as_secs();
+    if time.subsec_nanos() > 0 {
+        secs += 1;
+    }
+    set_resource_limit(command, libc::RLIMIT_CPU, secs, secs + 1);
+}
+
+fn set_resource_limit(command: &mut Command, resource: libc::__rlimit_resource_t, soft: u64, hard: u64) {
+    if soft == 0 {
+        return;
+    }
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t
+    };
+    // only async-signal-safe functions are called between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setrlimit(resource, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// start the process of the command in a new process group led by itself
+// so that it can be killed with the processes it starts
+pub fn set_process_group(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// kill all the processes in the process group led by the child
+// the child should be started with set_process_group
+pub fn kill_process_group(child: &Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+// the messages of a failed allocation in the log of a process
+// from rust, c++, python, java, go and the c library
+const OUT_OF_MEMORY_MESSAGES: [&str; 7] = [
+    "memory allocation of",
+    "std::bad_alloc",
+    "MemoryError",
+    "OutOfMemoryError",
+    "out of memory",
+    "virtual memory exhausted",
+    "Cannot allocate memory"
+];
+
+// decide whether a process failed because of the memory limit
+// the peak memory is near the limit
+// or the process reports a failed allocation under the address space limit in its log
+// other failures, such as an abort without such a message, are not counted
+pub fn is_out_of_memory(res: &WaitResult, bytes: u64, log: &[u8]) -> bool {
+    if bytes == 0 || res.status.success() {
+        return false;
+    }
+    // the address space is always larger than the resident memory
+    if res.usage.memory >= bytes / 10 * 9 {
+        return true;
+    }
+    let log = String::from_utf8_lossy(log);
+    return OUT_OF_MEMORY_MESSAGES.iter().any(|msg| { log.contains(msg) });
+}
+
+// decide whether a process failed because of the output limit
+// the process is killed by SIGXFSZ
+// or it ignores the signal and the output reaches the limit
+pub fn is_output_limit_exceeded(res: &WaitResult, output_size: u64, bytes: u64) -> bool {
+    if bytes == 0 {
+        return false;
+    }
+    return res.status.signal() == Some(libc::SIGXFSZ) || output_size >= bytes;
+}
+
+// decide whether a process used up the cpu time limit
+// the cpu time is over the limit or the process is killed by SIGXCPU
+pub fn is_cpu_time_exceeded(res: &WaitResult, time: Duration) -> bool {
+    if time.is_zero() {
+        return false;
+    }
+    return res.usage.cpu_time > time || res.status.signal() == Some(libc::SIGXCPU);
+}
+
+// describe how a failed process exits for the user
+// the name of the signal or the exit code
+pub fn describe_status(status: &ExitStatus) -> String {
+    if let Some(sig) = status.signal() {
+        return signal_name(sig);
+    }
+    match status.code() {
+        // rust programs exit with 101 on panic
+        Some(101) => "exit code 101 (panic)".to_string(),
+        Some(code) => format!("exit code {}", code),
+        None => "unknown exit status".to_string(),
+    }
+}
+
+fn signal_name(sig: c_int) -> String {
+    let name = match sig {
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGSYS => "SIGSYS",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGINT => "SIGINT",
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return format!("signal {}", sig),
+    };
+    name.to_string()
+}
+
+fn to_duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+}
+
+// some simple tests
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_describe_status() {
+        let status = |script: &str| {
+            Command::new("sh").args(["-c", script]).status().unwrap()
+        };
+        assert_eq!(describe_status(&status("exit 3")), "exit code 3");
+        assert_eq!(describe_status(&status("exit 101")), "exit code 101 (panic)");
+        assert_eq!(describe_status(&status("kill -SEGV $$")), "SIGSEGV");
+        assert_eq!(describe_status(&status("kill -FPE $$")), "SIGFPE");
+        assert_eq!(describe_status(&status("kill -USR1 $$")), format!("signal {}", libc::SIGUSR1));
+    }
+    #[test]
+    fn test_capture() {
+        let mut child = Command::new("sh").args(["-c", "head -c 100000 /dev/zero >&2; echo end >&2"])
+            .stderr(std::process::Stdio::piped()).spawn().unwrap();
+        let capture = capture(child.stderr.take().unwrap(), 10, 4);
+        child.wait().unwrap();
+        let captured = capture.finish();
+        assert_eq!(captured.size, 100004);
+        assert_eq!(captured.head, vec![0; 10]);
+        assert_eq!(captured.tail, b"end\n");
+    }
+    #[test]
+    fn test_out_of_memory() {
+        let run = |script: &str| {
+            let mut child = Command::new("sh").args(["-c", script]).spawn().unwrap();
+            wait_with_usage(&mut child, Duration::from_secs(5)).unwrap()
+        };
+        let limit = 1024 * 1024 * 1024;
+        // an abort is not a memory limit by itself
+        let res = run("kill -ABRT $$");
+        assert!(!is_out_of_memory(&res, limit, b"assertion failed"));
+        assert!(is_out_of_memory(&res, limit, b"memory allocation of 41943040 bytes failed\n"));
+        assert!(is_out_of_memory(&res, limit, b"terminate called after throwing an instance of 'std::bad_alloc'"));
+        // no limit or a normal exit
+        assert!(!is_out_of_memory(&res, 0, b"MemoryError"));
+        assert!(!is_out_of_memory(&run("exit 0"), limit, b"MemoryError"));
+        // the peak memory near the limit
+        let res = run("exit 1");
+        assert!(is_out_of_memory(&res, res.usage.memory, b""));
+    }
+}
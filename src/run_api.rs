@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use actix_web::{post, web, HttpResponse};
+use log;
+use serde::{Serialize, Deserialize};
+
+use crate::archive;
+use crate::config::Config;
+use crate::job::{JobInfo, Job};
+use crate::{worker, AppError};
+
+
+// the id of the next custom run, which only names its directory
+static NEXT_RUN_ID: AtomicU32 = AtomicU32::new(0);
+
+// the json content of the post run http request
+// the source code or the archive is run once on the input
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunInfo {
+    #[serde(default)]
+    pub source_code: String,
+    pub language: String,
+    #[serde(default, alias = "stdin")]
+    pub input: String,
+    #[serde(default)]
+    pub archive: Option<String>
+}
+
+// run a submission on the input of the user with the default limits
+// the run is done by the judge workers but it is not stored
+// so it does not count for the submission limit of the contests
+#[post("/runs")]
+pub async fn post_runs(info: web::Json<RunInfo>, config: web::Data<Config>) -> Result<HttpResponse, AppError> {
+    let info = info.into_inner();
+    if !config.languages.iter().any(|item| { item.name==info.language }) {
+        return Err(AppError::ERR_NOT_FOUND);
+    }
+    if let Some(archive) = &info.archive {
+        archive::decode(archive, &config.server).map_err(|_| AppError::ERR_INVALID_ARGUMENT)?;
+    }
+    let job_info = JobInfo {
+        source_code: info.source_code,
+        language: info.language,
+        user_id: 0,
+        contest_id: 0,
+        problem_id: 0,
+        archive: info.archive
+    };
+    let run_id = NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed);
+    let receiver = worker::push_run(Job::new_custom_run(run_id, &job_info), info.input);
+    // wait for the worker without blocking the http worker
+    let output = web::block(move || { receiver.recv() }).await
+        .map_err(|_| AppError::ERR_INTERNAL)?
+        .map_err(|_| AppError::ERR_INTERNAL)?;
+    log::info!(target: "post_runs", "custom run {} finished", run_id);
+    return Ok(HttpResponse::Ok().json(output));
+}
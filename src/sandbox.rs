@@ -0,0 +1,446 @@
+use std::collections::BTreeSet;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::raw::{c_int, c_long, c_uint, c_ulong, c_ushort};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{config, storage};
+
+
+// the audit arch of the seccomp data
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+// the x32 syscalls on x86_64 have this bit set
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+const ST_RELATIME: c_ulong = 4096;
+const CLONE_NAMESPACES: c_int = libc::CLONE_NEWNS | libc::CLONE_NEWCGROUP | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC | libc::CLONE_NEWUSER | libc::CLONE_NEWPID | libc::CLONE_NEWNET;
+
+// the offsets of the fields in libc::seccomp_data
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+const DATA_ARG0: u32 = 16;
+
+// the syscalls a judged program is allowed to make
+// clone can not create namespaces, see seccomp_filter
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const ALLOWED_SYSCALLS: &[c_long] = &[
+    libc::SYS_read, libc::SYS_write, libc::SYS_readv, libc::SYS_writev,
+    libc::SYS_pread64, libc::SYS_pwrite64, libc::SYS_lseek, libc::SYS_close,
+    libc::SYS_openat, libc::SYS_fstat, libc::SYS_newfstatat, libc::SYS_statx,
+    libc::SYS_faccessat, libc::SYS_faccessat2, libc::SYS_readlinkat, libc::SYS_getcwd,
+    libc::SYS_getdents64, libc::SYS_fcntl, libc::SYS_ioctl, libc::SYS_dup, libc::SYS_dup3,
+    libc::SYS_pipe2, libc::SYS_ppoll, libc::SYS_pselect6,
+    libc::SYS_brk, libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mremap, libc::SYS_mprotect,
+    libc::SYS_madvise, libc::SYS_membarrier,
+    libc::SYS_rt_sigaction, libc::SYS_rt_sigprocmask, libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack, libc::SYS_tgkill, libc::SYS_gettid, libc::SYS_getpid,
+    libc::SYS_getppid, libc::SYS_getuid, libc::SYS_geteuid, libc::SYS_getgid, libc::SYS_getegid,
+    libc::SYS_execve, libc::SYS_exit, libc::SYS_exit_group, libc::SYS_wait4, libc::SYS_waitid,
+    libc::SYS_kill, libc::SYS_getpgid, libc::SYS_setpgid, libc::SYS_getsid, libc::SYS_setsid,
+    libc::SYS_chdir, libc::SYS_fchdir, libc::SYS_umask, libc::SYS_fstatfs, libc::SYS_statfs,
+    libc::SYS_unlinkat, libc::SYS_renameat2, libc::SYS_mkdirat, libc::SYS_fchmod, libc::SYS_ftruncate,
+    libc::SYS_fsync, libc::SYS_flock, libc::SYS_rt_sigsuspend,
+    libc::SYS_rt_sigtimedwait, libc::SYS_epoll_create1, libc::SYS_epoll_ctl, libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2, libc::SYS_getresuid, libc::SYS_getresgid, libc::SYS_getgroups,
+    libc::SYS_sched_getparam, libc::SYS_sched_getscheduler, libc::SYS_getpriority,
+    libc::SYS_set_tid_address, libc::SYS_set_robust_list, libc::SYS_rseq, libc::SYS_futex,
+    libc::SYS_sched_yield, libc::SYS_sched_getaffinity, libc::SYS_prlimit64,
+    libc::SYS_getrusage, libc::SYS_getrandom, libc::SYS_uname, libc::SYS_sysinfo, libc::SYS_times,
+    libc::SYS_clock_gettime, libc::SYS_clock_getres, libc::SYS_clock_nanosleep,
+    libc::SYS_gettimeofday, libc::SYS_nanosleep,
+    #[cfg(target_arch = "x86_64")] libc::SYS_open,
+    #[cfg(target_arch = "x86_64")] libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")] libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")] libc::SYS_access,
+    #[cfg(target_arch = "x86_64")] libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")] libc::SYS_dup2,
+    #[cfg(target_arch = "x86_64")] libc::SYS_pipe,
+    #[cfg(target_arch = "x86_64")] libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")] libc::SYS_select,
+    #[cfg(target_arch = "x86_64")] libc::SYS_arch_prctl,
+    #[cfg(target_arch = "x86_64")] libc::SYS_time,
+    #[cfg(target_arch = "x86_64")] libc::SYS_getrlimit,
+    #[cfg(target_arch = "x86_64")] libc::SYS_fadvise64,
+    #[cfg(target_arch = "x86_64")] libc::SYS_fork,
+    #[cfg(target_arch = "x86_64")] libc::SYS_vfork,
+    #[cfg(target_arch = "x86_64")] libc::SYS_getpgrp,
+    #[cfg(target_arch = "x86_64")] libc::SYS_unlink,
+    #[cfg(target_arch = "x86_64")] libc::SYS_rename,
+    #[cfg(target_arch = "x86_64")] libc::SYS_renameat,
+    #[cfg(target_arch = "x86_64")] libc::SYS_mkdir,
+    #[cfg(target_arch = "x86_64")] libc::SYS_epoll_create,
+    #[cfg(target_arch = "x86_64")] libc::SYS_epoll_wait,
+];
+
+// the syscalls the compilers and make need besides ALLOWED_SYSCALLS
+// rustc waits for the linker through a socket pair, make resets the ids before exec
+// and the linker makes the output executable
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const COMPILER_SYSCALLS: &[c_long] = &[
+    libc::SYS_socketpair, libc::SYS_recvfrom, libc::SYS_setresuid, libc::SYS_setresgid,
+    libc::SYS_fchmodat,
+    #[cfg(target_arch = "x86_64")] libc::SYS_chmod,
+];
+
+// run the program started by the command in a sandbox
+// the program gets new user, mount, pid, network, ipc and uts namespaces
+// all the mounts are read only, the only writable directory is the work dir
+// the other directories next to the work dir and the hidden paths are not visible
+// /proc only shows the processes of the sandbox
+// the syscalls are limited by a seccomp allowlist
+// the current dir of the command, or of the server, is entered again after the mounts
+// an error is returned by spawn if the sandbox can not be set up
+pub fn apply(command: &mut Command, config: &config::Config, work_dir: &Path) -> io::Result<()> {
+    return setup(command, config, work_dir, &[]);
+}
+
+// run the compiler, or the build of a project, in the same sandbox as the program
+// so that the hidden files can not be included into the program
+// the compiler needs a few more syscalls than the program, see COMPILER_SYSCALLS
+pub fn apply_to_compiler(command: &mut Command, config: &config::Config, work_dir: &Path) -> io::Result<()> {
+    return setup(command, config, work_dir, COMPILER_SYSCALLS);
+}
+
+fn setup(command: &mut Command, config: &config::Config, work_dir: &Path, extra_syscalls: &[c_long]) -> io::Result<()> {
+    let sandbox = &config.sandbox;
+    let current_dir = match command.get_current_dir() {
+        Some(dir) => fs::canonicalize(dir)?,
+        None => std::env::current_dir()?,
+    };
+    let current_dir = cstring(&current_dir)?;
+    let work_dir = fs::canonicalize(work_dir)?;
+    let tmp_dir = work_dir.parent().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "work dir has no parent")
+    })?;
+    let hidden = hidden_paths(config, &work_dir, tmp_dir)?;
+    let work_dir = cstring(&work_dir)?;
+    let tmp_dir = cstring(tmp_dir)?;
+    let mounts = mount_points()?;
+    let uid_map = format!("0 {} 1", unsafe { libc::geteuid() });
+    let gid_map = format!("0 {} 1", unsafe { libc::getegid() });
+    let filter = seccomp_filter(extra_syscalls);
+    let nproc = libc::rlimit {
+        rlim_cur: sandbox.max_processes as libc::rlim_t,
+        rlim_max: sandbox.max_processes as libc::rlim_t
+    };
+
+    // only async-signal-safe functions are called between fork and exec
+    // so everything above is prepared before the fork
+    unsafe {
+        command.pre_exec(move || {
+            let flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWPID
+                | libc::CLONE_NEWNET | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS;
+            check(libc::unshare(flags))?;
+            write_file(b"/proc/self/setgroups\0", b"deny")?;
+            write_file(b"/proc/self/uid_map\0", uid_map.as_bytes())?;
+            write_file(b"/proc/self/gid_map\0", gid_map.as_bytes())?;
+            // do not propagate the mounts back to the host
+            check(libc::mount(b"none\0".as_ptr().cast(), b"/\0".as_ptr().cast(), std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()))?;
+
+            // cover the other jobs with an empty tmpfs
+            // and bind the work dir back to where it was
+            let fd = check(libc::open(work_dir.as_ptr(), libc::O_PATH | libc::O_CLOEXEC))?;
+            check(libc::mount(b"tmpfs\0".as_ptr().cast(), tmp_dir.as_ptr(), b"tmpfs\0".as_ptr().cast(),
+                libc::MS_NOSUID | libc::MS_NODEV, b"size=16k,mode=755\0".as_ptr().cast()))?;
+            check(libc::mkdir(work_dir.as_ptr(), 0o755))?;
+            let mut source = [0u8; 32];
+            fd_path(fd, &mut source);
+            check(libc::mount(source.as_ptr().cast(), work_dir.as_ptr(), std::ptr::null(),
+                libc::MS_BIND, std::ptr::null()))?;
+            libc::close(fd);
+            check(remount_read_only(&tmp_dir))?;
+
+            // the bind mount above is a new mount and stays writable
+            for mount in mounts.iter() {
+                if remount_read_only(mount) != 0 {
+                    let err = io::Error::last_os_error();
+                    // the mount is covered by another mount
+                    if err.raw_os_error() != Some(libc::ENOENT) && err.raw_os_error() != Some(libc::EACCES) {
+                        return Err(err);
+                    }
+                }
+            }
+            for (path, is_dir) in hidden.iter() {
+                if *is_dir {
+                    check(libc::mount(b"tmpfs\0".as_ptr().cast(), path.as_ptr(), b"tmpfs\0".as_ptr().cast(),
+                        libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, std::ptr::null()))?;
+                } else {
+                    check(libc::mount(b"/dev/null\0".as_ptr().cast(), path.as_ptr(), std::ptr::null(),
+                        libc::MS_BIND, std::ptr::null()))?;
+                }
+            }
+            // the current dir still points to the mount before the remount
+            check(libc::chdir(current_dir.as_ptr()))?;
+
+            // the first process forked after unshare is the init of the new pid namespace
+            // the init ignores the signals it sends to itself, such as the SIGABRT of abort
+            // so the program runs as the child of the init
+            // the init reports the status of the program to this process through a pipe
+            // and this process outside of the namespace exits with the same status
+            let mut pipe = [0; 2];
+            check(libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC))?;
+            let init = check(libc::fork())?;
+            if init != 0 {
+                // do not keep the pipe of the spawn open, the program will exec
+                close_fds_except(pipe[0]);
+                let status = wait_child(init);
+                let mut report: c_int = 0;
+                let size = std::mem::size_of::<c_int>();
+                if libc::read(pipe[0], (&mut report as *mut c_int).cast(), size) == size as isize {
+                    exit_with(report);
+                }
+                exit_with(status);
+            }
+
+            // the sandbox is gone if the process above is killed because of the time limit
+            check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL))?;
+            check(libc::mount(b"proc\0".as_ptr().cast(), b"/proc\0".as_ptr().cast(), b"proc\0".as_ptr().cast(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, std::ptr::null()))?;
+            let program = check(libc::fork())?;
+            if program != 0 {
+                close_fds_except(pipe[1]);
+                let status = wait_child(program);
+                let size = std::mem::size_of::<c_int>();
+                libc::write(pipe[1], (&status as *const c_int).cast(), size);
+                libc::_exit(0);
+            }
+
+            check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL))?;
+            // the processes are counted in the new user namespace
+            if nproc.rlim_cur != 0 {
+                check(libc::setrlimit(libc::RLIMIT_NPROC, &nproc))?;
+            }
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            let prog = libc::sock_fprog {
+                len: filter.len() as c_ushort,
+                filter: filter.as_ptr() as *mut libc::sock_filter
+            };
+            check(libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog))?;
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+// the paths hidden from the program and whether they are directories
+// the hidden paths of the sandbox, the data files of the storage and the files of every case
+// the directory of a data file or a case file is hidden as a whole
+// unless the work dir is in it, then only the file is hidden
+// a path in a hidden directory or in the tmp dir, which is already covered, is left out
+fn hidden_paths(config: &config::Config, work_dir: &Path, tmp_dir: &Path) -> io::Result<Vec<(CString, bool)>> {
+    let mut paths = BTreeSet::new();
+    for path in config.sandbox.hidden_paths.iter() {
+        if let Some(path) = canonicalize(Path::new(path))? {
+            paths.insert(path);
+        }
+    }
+    let cases = config.problems.iter().flat_map(|problem| { problem.cases.iter() });
+    let files = cases.flat_map(|case| { [&case.input_file, &case.answer_file] });
+    let data_file = &config.server.data_file;
+    let data = [data_file.clone(), storage::journal_file(data_file), storage::archive_dir(data_file)];
+    for file in files.chain(data.iter()) {
+        let file = match canonicalize(Path::new(file))? {
+            Some(file) => file,
+            None => continue,
+        };
+        match file.parent() {
+            Some(dir) if !work_dir.starts_with(dir) => paths.insert(dir.to_path_buf()),
+            _ => paths.insert(file),
+        };
+    }
+    // the paths are sorted so a directory comes before the paths in it
+    let mut hidden: Vec<(PathBuf, bool)> = Vec::new();
+    for path in paths {
+        if path.starts_with(tmp_dir) || hidden.iter().any(|(dir, is_dir)| { *is_dir && path.starts_with(dir) }) {
+            continue;
+        }
+        let is_dir = fs::metadata(&path)?.is_dir();
+        hidden.push((path, is_dir));
+    }
+    hidden.into_iter().map(|(path, is_dir)| { Ok((cstring(&path)?, is_dir)) }).collect()
+}
+
+// the absolute path, or None if there is nothing to hide
+fn canonicalize(path: &Path) -> io::Result<Option<PathBuf>> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(Some(path)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// the seccomp filter allowing the syscalls in ALLOWED_SYSCALLS and the extra ones
+// clone is allowed without the namespace flags
+// clone3 fails with ENOSYS so that the libc falls back to clone, whose flags can be checked
+// any other syscall fails with EPERM
+fn seccomp_filter(extra_syscalls: &[c_long]) -> Vec<libc::sock_filter> {
+    let stmt = |code: u32, k: u32| libc::sock_filter { code: code as u16, jt: 0, jf: 0, k };
+    let jump = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter { code: code as u16, jt, jf, k };
+    let allow = libc::SECCOMP_RET_ALLOW;
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    let mut filter = vec![
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, DATA_ARCH),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, DATA_NR),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    {
+        filter.push(jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, X32_SYSCALL_BIT, 0, 1));
+        filter.push(stmt(libc::BPF_RET | libc::BPF_K, deny));
+    }
+    for nr in ALLOWED_SYSCALLS.iter().chain(extra_syscalls) {
+        filter.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, *nr as u32, 0, 1));
+        filter.push(stmt(libc::BPF_RET | libc::BPF_K, allow));
+    }
+    filter.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_clone3 as u32, 0, 1));
+    filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32));
+    filter.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_clone as u32, 0, 3));
+    filter.push(stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, DATA_ARG0));
+    filter.push(jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, CLONE_NAMESPACES as u32, 1, 0));
+    filter.push(stmt(libc::BPF_RET | libc::BPF_K, allow));
+    filter.push(stmt(libc::BPF_RET | libc::BPF_K, deny));
+    filter
+}
+
+// the mount points of the current mount namespace
+fn mount_points() -> io::Result<Vec<CString>> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    let mut mounts = Vec::new();
+    for line in mountinfo.lines() {
+        if let Some(path) = line.split(' ').nth(4) {
+            mounts.push(CString::new(unescape(path))?);
+        }
+    }
+    Ok(mounts)
+}
+
+// mountinfo escapes space, tab, newline and backslash as octal
+fn unescape(path: &str) -> Vec<u8> {
+    let bytes = path.as_bytes();
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            if let Ok(c) = u8::from_str_radix(&path[i+1..i+4], 8) {
+                res.push(c);
+                i += 4;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    res
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+// make a mount read only and keep the other flags
+// the flags locked by the parent namespace can not be cleared
+unsafe fn remount_read_only(path: &CString) -> c_int {
+    let mut stat: libc::statvfs = std::mem::zeroed();
+    if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+        return -1;
+    }
+    let mut flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY;
+    let keep = [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (ST_RELATIME, libc::MS_RELATIME),
+    ];
+    for (st, ms) in keep.iter() {
+        if stat.f_flag & st != 0 {
+            flags |= ms;
+        }
+    }
+    libc::mount(std::ptr::null(), path.as_ptr(), std::ptr::null(), flags, std::ptr::null())
+}
+
+// write /proc/self/fd/<fd> to the buffer without allocating
+fn fd_path(fd: c_int, buf: &mut [u8; 32]) {
+    let prefix = b"/proc/self/fd/";
+    buf[..prefix.len()].copy_from_slice(prefix);
+    let mut digits = [0u8; 10];
+    let mut n = fd as u32;
+    let mut len = 0;
+    loop {
+        digits[len] = b'0' + (n % 10) as u8;
+        len += 1;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    for i in 0..len {
+        buf[prefix.len() + i] = digits[len - 1 - i];
+    }
+    buf[prefix.len() + len] = 0;
+}
+
+// close all the fds except stdio and the given one
+unsafe fn close_fds_except(keep: c_int) {
+    let close_range = |first: c_int, last: c_uint| {
+        libc::syscall(libc::SYS_close_range, first as c_uint, last, 0)
+    };
+    if close_range(3, (keep - 1) as c_uint) != 0 || close_range(keep + 1, c_uint::MAX) != 0 {
+        // close_range is not supported by the kernel
+        for fd in (3..1024).filter(|fd| *fd != keep) {
+            libc::close(fd);
+        }
+    }
+}
+
+// wait the child process and return the wait status
+// 127 is returned as the exit code if the wait fails
+unsafe fn wait_child(pid: libc::pid_t) -> c_int {
+    let mut status = 0;
+    while libc::waitpid(pid, &mut status, 0) == -1 {
+        if *libc::__errno_location() != libc::EINTR {
+            return 127 << 8;
+        }
+    }
+    status
+}
+
+// exit with the same wait status, either an exit code or a signal
+unsafe fn exit_with(status: c_int) -> ! {
+    if libc::WIFSIGNALED(status) {
+        let sig = libc::WTERMSIG(status);
+        libc::signal(sig, libc::SIG_DFL);
+        libc::kill(libc::getpid(), sig);
+    }
+    libc::_exit(libc::WEXITSTATUS(status));
+}
+
+unsafe fn write_file(path: &[u8], content: &[u8]) -> io::Result<()> {
+    let fd = check(libc::open(path.as_ptr().cast(), libc::O_WRONLY | libc::O_CLOEXEC))?;
+    let ret = libc::write(fd, content.as_ptr().cast(), content.len());
+    libc::close(fd);
+    if ret != content.len() as isize {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn check(ret: c_int) -> io::Result<c_int> {
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
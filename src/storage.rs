@@ -0,0 +1,602 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::job::Job;
+use crate::contest_api::ContestInfo;
+use crate::{User, AppError, ContestEntry};
+
+
+// the repository operations of the jobs, users and contests
+// the find functions return ERR_NOT_FOUND if there is no such item
+// the insert and update functions return ERR_INTERNAL if the change can not be stored
+pub trait Storage: Send {
+    // all the jobs in the order of insertion
+    fn job_list(&self) -> Vec<&Job>;
+    // the id of the next inserted job
+    fn next_job_id(&self) -> u32;
+    fn find_job(&self, job_id: u32) -> Result<&Job, AppError>;
+    fn insert_job(&mut self, job: Job) -> Result<(), AppError>;
+    fn update_job(&mut self, job: Job) -> Result<(), AppError>;
+    fn remove_job(&mut self, job_id: u32) -> Result<Job, AppError>;
+    // insert the job together with its contest, whose submission count is updated
+    // nothing is changed if any of them can not be stored
+    fn submit_job(&mut self, job: Job, contest: Option<ContestEntry>) -> Result<(), AppError>;
+
+    // all the users in the order of insertion
+    fn user_list(&self) -> Vec<&User>;
+    // the id of the next inserted user
+    fn next_user_id(&self) -> u32;
+    fn find_user(&self, user_id: u32) -> Result<&User, AppError>;
+    fn insert_user(&mut self, user: User) -> Result<(), AppError>;
+    fn update_user(&mut self, user: User) -> Result<(), AppError>;
+
+    // all the contests in the order of insertion
+    fn contest_list(&self) -> Vec<&ContestEntry>;
+    // the id of the next inserted contest
+    fn next_contest_id(&self) -> u32;
+    fn find_contest(&self, contest_id: u32) -> Result<&ContestEntry, AppError>;
+    fn insert_contest(&mut self, contest: ContestEntry) -> Result<(), AppError>;
+    fn update_contest(&mut self, contest: ContestEntry) -> Result<(), AppError>;
+}
+
+// keep all the data in memory
+// the data is lost when the server stops
+pub struct MemoryStorage {
+    job_list: Vec<Job>,
+    total_jobs: u32,
+    user_list: Vec<User>,
+    total_users: u32,
+    contests_list: Vec<ContestEntry>,
+    total_contests: u32,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        let user_list = vec![User{
+            id: 0,
+            name: "root".to_string()
+        }];
+        Self {
+            job_list: Vec::new(),
+            total_jobs: 0,
+            user_list,
+            total_users: 1,
+            contests_list: Vec::new(),
+            total_contests: 1
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn job_list(&self) -> Vec<&Job> {
+        self.job_list.iter().collect()
+    }
+    fn next_job_id(&self) -> u32 {
+        self.total_jobs
+    }
+    fn find_job(&self, job_id: u32) -> Result<&Job, AppError> {
+        let response = self.job_list.iter().find(|x| {
+            x.job_id==job_id
+        });
+        return response.ok_or(AppError::ERR_NOT_FOUND);
+    }
+    fn insert_job(&mut self, job: Job) -> Result<(), AppError> {
+        self.total_jobs = self.total_jobs.max(job.job_id + 1);
+        self.job_list.push(job);
+        Ok(())
+    }
+    fn update_job(&mut self, job: Job) -> Result<(), AppError> {
+        let pos = self.job_list.iter_mut().find(|x| {
+            x.job_id==job.job_id
+        }).ok_or(AppError::ERR_NOT_FOUND)?;
+        *pos = job;
+        Ok(())
+    }
+    fn remove_job(&mut self, job_id: u32) -> Result<Job, AppError> {
+        let idx = self.job_list.iter().position(|x| {
+            x.job_id==job_id
+        }).ok_or(AppError::ERR_NOT_FOUND)?;
+        Ok(self.job_list.remove(idx))
+    }
+    fn submit_job(&mut self, job: Job, contest: Option<ContestEntry>) -> Result<(), AppError> {
+        if let Some(contest) = contest {
+            self.find_contest(contest.0.id)?;
+            self.update_contest(contest)?;
+        }
+        self.insert_job(job)
+    }
+
+    fn user_list(&self) -> Vec<&User> {
+        self.user_list.iter().collect()
+    }
+    fn next_user_id(&self) -> u32 {
+        self.total_users
+    }
+    fn find_user(&self, user_id: u32) -> Result<&User, AppError> {
+        let response = self.user_list.iter().find(|x| {
+            x.id==user_id
+        });
+        return response.ok_or(AppError::ERR_NOT_FOUND);
+    }
+    fn insert_user(&mut self, user: User) -> Result<(), AppError> {
+        self.total_users = self.total_users.max(user.id + 1);
+        self.user_list.push(user);
+        Ok(())
+    }
+    fn update_user(&mut self, user: User) -> Result<(), AppError> {
+        let pos = self.user_list.iter_mut().find(|x| {
+            x.id==user.id
+        }).ok_or(AppError::ERR_NOT_FOUND)?;
+        *pos = user;
+        Ok(())
+    }
+
+    fn contest_list(&self) -> Vec<&ContestEntry> {
+        self.contests_list.iter().collect()
+    }
+    fn next_contest_id(&self) -> u32 {
+        self.total_contests
+    }
+    fn find_contest(&self, contest_id: u32) -> Result<&ContestEntry, AppError> {
+        let response = self.contests_list.iter().find(|x| {
+            x.0.id==contest_id
+        });
+        return response.ok_or(AppError::ERR_NOT_FOUND);
+    }
+    fn insert_contest(&mut self, contest: ContestEntry) -> Result<(), AppError> {
+        self.total_contests = self.total_contests.max(contest.0.id + 1);
+        self.contests_list.push(contest);
+        Ok(())
+    }
+    fn update_contest(&mut self, contest: ContestEntry) -> Result<(), AppError> {
+        let pos = self.contests_list.iter_mut().find(|x| {
+            x.0.id==contest.0.id
+        }).ok_or(AppError::ERR_NOT_FOUND)?;
+        *pos = contest;
+        Ok(())
+    }
+}
+
+// the number of records in the journal before a new snapshot is taken
+const SNAPSHOT_INTERVAL: u64 = 1000;
+
+// the content of the data file
+// the submission times map is stored as a list
+// because json only supports string keys
+type SavedContest = (ContestInfo, Vec<((u32, u32), u32)>);
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    // the sequence number of the last record in the snapshot
+    #[serde(default)]
+    sequence: u64,
+    job_list: Vec<Job>,
+    total_jobs: u32,
+    user_list: Vec<User>,
+    total_users: u32,
+    contests_list: Vec<SavedContest>,
+    total_contests: u32,
+}
+
+// a change of the data, one line of the journal with its sequence number
+#[derive(Serialize, Deserialize)]
+enum Record {
+    InsertJob(Job),
+    UpdateJob(Job),
+    RemoveJob(u32),
+    SubmitJob(Job, Option<SavedContest>),
+    InsertUser(User),
+    UpdateUser(User),
+    InsertContest(SavedContest),
+    UpdateContest(SavedContest),
+}
+
+// keep the data in memory and append each change to the journal next to the data file
+// a change is written and synced to the journal before it is applied in memory
+// the data file is a snapshot of all the data, which is taken when the storage is opened
+// and after every SNAPSHOT_INTERVAL records, then the journal starts again
+// the archive of a job is left out of the records and written once to the archive dir
+pub struct FileStorage {
+    memory: MemoryStorage,
+    data_file: String,
+    journal: File,
+    // the size of the journal without a partly written record
+    journal_len: u64,
+    // the sequence number of the last record
+    sequence: u64,
+    // the number of records since the last snapshot
+    records: u64,
+}
+
+impl FileStorage {
+    // load the snapshot in the data file and replay the journal
+    // start with the default data if the files do not exist
+    pub fn open(data_file: &str) -> io::Result<Self> {
+        let mut memory = MemoryStorage::default();
+        let mut sequence = 0;
+        if Path::new(data_file).is_file() {
+            let json = fs::read_to_string(data_file)?;
+            let snapshot: Snapshot = serde_json::from_str(&json)?;
+            sequence = snapshot.sequence;
+            memory.job_list = snapshot.job_list;
+            memory.total_jobs = snapshot.total_jobs;
+            memory.user_list = snapshot.user_list;
+            memory.total_users = snapshot.total_users;
+            memory.contests_list = snapshot.contests_list.into_iter().map(load_contest).collect();
+            memory.total_contests = snapshot.total_contests;
+        }
+        let journal_file = journal_file(data_file);
+        if Path::new(&journal_file).is_file() {
+            let content = fs::read(&journal_file)?;
+            let lines: Vec<&[u8]> = content.split(|c| { *c == b'\n' }).collect();
+            // the last line is empty, or a record torn when the server stopped
+            for line in lines[..lines.len() - 1].iter() {
+                let (seq, record): (u64, Record) = serde_json::from_slice(line)?;
+                // the records before a snapshot are left if the server stopped
+                // between the snapshot and emptying the journal
+                if seq <= sequence {
+                    continue;
+                }
+                apply(&mut memory, record).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("invalid record {} in the journal", seq))
+                })?;
+                sequence = seq;
+            }
+        }
+        if let Some(dir) = Path::new(data_file).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // the archives of the jobs loaded above are read back
+        // an archive still in a record of an older version is written to the archive dir
+        fs::create_dir_all(archive_dir(data_file))?;
+        for job in memory.job_list.iter_mut() {
+            let file = archive_file(data_file, job.job_id);
+            if job.info.archive.is_some() {
+                save_archive(data_file, job)?;
+            } else if Path::new(&file).is_file() {
+                job.info.archive = Some(fs::read_to_string(&file)?);
+            }
+        }
+        let journal = OpenOptions::new().create(true).append(true).open(&journal_file)?;
+        let mut storage = Self {
+            memory,
+            data_file: data_file.to_string(),
+            journal,
+            journal_len: 0,
+            sequence,
+            records: 0
+        };
+        storage.snapshot()?;
+        Ok(storage)
+    }
+    // remove the data file, the journal and the archives
+    pub fn flush(data_file: &str) -> io::Result<()> {
+        for file in [data_file.to_string(), journal_file(data_file)] {
+            if Path::new(&file).is_file() {
+                fs::remove_file(&file)?;
+            }
+        }
+        let dir = archive_dir(data_file);
+        if Path::new(&dir).is_dir() {
+            fs::remove_dir_all(&dir)?;
+        }
+        Ok(())
+    }
+    // save all the data to the data file and empty the journal
+    // write a temp file first and then rename it
+    // so that the data file is never half written
+    // the file and the directory are synced before the journal is emptied
+    fn snapshot(&mut self) -> io::Result<()> {
+        let memory = &self.memory;
+        let snapshot = Snapshot {
+            sequence: self.sequence,
+            job_list: memory.job_list.clone(),
+            total_jobs: memory.total_jobs,
+            user_list: memory.user_list.clone(),
+            total_users: memory.total_users,
+            contests_list: memory.contests_list.iter().map(save_contest).collect(),
+            total_contests: memory.total_contests,
+        };
+        let temp_file = format!("{}.tmp", self.data_file);
+        let mut file = File::create(&temp_file)?;
+        file.write_all(&serde_json::to_vec(&snapshot)?)?;
+        file.sync_all()?;
+        fs::rename(&temp_file, &self.data_file)?;
+        let dir = match Path::new(&self.data_file).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+        self.journal.set_len(0)?;
+        self.journal_len = 0;
+        self.records = 0;
+        self.journal.sync_all()?;
+        Ok(())
+    }
+    fn write_archive(&self, job: &Job) -> Result<(), AppError> {
+        save_archive(&self.data_file, job).map_err(|e| {
+            log::error!(target: "FileStorage::write_archive", "Write archive failed {}", e);
+            AppError::ERR_INTERNAL
+        })
+    }
+    // write the record to the journal and then apply it in memory
+    // the caller checks that the record can be applied
+    // nothing is changed if the record can not be written
+    fn commit(&mut self, record: Record) -> Result<(), AppError> {
+        let sequence = self.sequence + 1;
+        let mut try_do = || -> io::Result<u64> {
+            let mut line = serde_json::to_vec(&(sequence, &record))?;
+            line.push(b'\n');
+            self.journal.write_all(&line)?;
+            self.journal.sync_data()?;
+            Ok(line.len() as u64)
+        };
+        match try_do() {
+            Ok(len) => self.journal_len += len,
+            Err(e) => {
+                // do not leave a partly written record before the next one
+                let _ = self.journal.set_len(self.journal_len);
+                log::error!(target: "FileStorage::commit", "Write journal failed {}", e);
+                return Err(AppError::ERR_INTERNAL);
+            }
+        }
+        self.sequence = sequence;
+        apply(&mut self.memory, record)?;
+        self.records += 1;
+        if self.records >= SNAPSHOT_INTERVAL {
+            // the records are still in the journal if the snapshot fails
+            if let Err(e) = self.snapshot() {
+                log::error!(target: "FileStorage::commit", "Save snapshot failed {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn journal_file(data_file: &str) -> String {
+    format!("{}.journal", data_file)
+}
+
+pub fn archive_dir(data_file: &str) -> String {
+    format!("{}.archives", data_file)
+}
+
+fn archive_file(data_file: &str, job_id: u32) -> String {
+    format!("{}/{}", archive_dir(data_file), job_id)
+}
+
+// write the archive of the job, if any, and sync it before the record of the job
+// a file left by a record that is not written is replaced by the next job with the id
+fn save_archive(data_file: &str, job: &Job) -> io::Result<()> {
+    if let Some(archive) = &job.info.archive {
+        let mut file = File::create(archive_file(data_file, job.job_id))?;
+        file.write_all(archive.as_bytes())?;
+        file.sync_all()?;
+        File::open(archive_dir(data_file))?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn save_contest(contest: &ContestEntry) -> SavedContest {
+    (contest.0.clone(), contest.1.iter().map(|(k, v)| (*k, *v)).collect())
+}
+
+fn load_contest(contest: SavedContest) -> ContestEntry {
+    (contest.0, contest.1.into_iter().collect::<HashMap<_, _>>())
+}
+
+// apply the change of the record in memory
+fn apply(memory: &mut MemoryStorage, record: Record) -> Result<(), AppError> {
+    match record {
+        Record::InsertJob(job) => memory.insert_job(job),
+        Record::UpdateJob(job) => memory.update_job(job),
+        Record::RemoveJob(job_id) => memory.remove_job(job_id).map(|_| ()),
+        Record::SubmitJob(job, contest) => memory.submit_job(job, contest.map(load_contest)),
+        Record::InsertUser(user) => memory.insert_user(user),
+        Record::UpdateUser(user) => memory.update_user(user),
+        Record::InsertContest(contest) => memory.insert_contest(load_contest(contest)),
+        Record::UpdateContest(contest) => memory.update_contest(load_contest(contest)),
+    }
+}
+
+impl Storage for FileStorage {
+    fn job_list(&self) -> Vec<&Job> {
+        self.memory.job_list()
+    }
+    fn next_job_id(&self) -> u32 {
+        self.memory.next_job_id()
+    }
+    fn find_job(&self, job_id: u32) -> Result<&Job, AppError> {
+        self.memory.find_job(job_id)
+    }
+    fn insert_job(&mut self, job: Job) -> Result<(), AppError> {
+        self.write_archive(&job)?;
+        self.commit(Record::InsertJob(job))
+    }
+    // the archive of a job does not change, it is kept in memory
+    fn update_job(&mut self, job: Job) -> Result<(), AppError> {
+        self.memory.find_job(job.job_id)?;
+        self.commit(Record::UpdateJob(job))
+    }
+    fn remove_job(&mut self, job_id: u32) -> Result<Job, AppError> {
+        let job = self.memory.find_job(job_id)?.clone();
+        self.commit(Record::RemoveJob(job_id))?;
+        if job.info.archive.is_some() {
+            // the job is already removed, a file left behind is only unused
+            if let Err(e) = fs::remove_file(archive_file(&self.data_file, job_id)) {
+                log::error!(target: "FileStorage::remove_job", "Remove archive failed {}", e);
+            }
+        }
+        Ok(job)
+    }
+    fn submit_job(&mut self, job: Job, contest: Option<ContestEntry>) -> Result<(), AppError> {
+        if let Some(contest) = contest.as_ref() {
+            self.memory.find_contest(contest.0.id)?;
+        }
+        self.write_archive(&job)?;
+        self.commit(Record::SubmitJob(job, contest.as_ref().map(save_contest)))
+    }
+
+    fn user_list(&self) -> Vec<&User> {
+        self.memory.user_list()
+    }
+    fn next_user_id(&self) -> u32 {
+        self.memory.next_user_id()
+    }
+    fn find_user(&self, user_id: u32) -> Result<&User, AppError> {
+        self.memory.find_user(user_id)
+    }
+    fn insert_user(&mut self, user: User) -> Result<(), AppError> {
+        self.commit(Record::InsertUser(user))
+    }
+    fn update_user(&mut self, user: User) -> Result<(), AppError> {
+        self.memory.find_user(user.id)?;
+        self.commit(Record::UpdateUser(user))
+    }
+
+    fn contest_list(&self) -> Vec<&ContestEntry> {
+        self.memory.contest_list()
+    }
+    fn next_contest_id(&self) -> u32 {
+        self.memory.next_contest_id()
+    }
+    fn find_contest(&self, contest_id: u32) -> Result<&ContestEntry, AppError> {
+        self.memory.find_contest(contest_id)
+    }
+    fn insert_contest(&mut self, contest: ContestEntry) -> Result<(), AppError> {
+        self.commit(Record::InsertContest(save_contest(&contest)))
+    }
+    fn update_contest(&mut self, contest: ContestEntry) -> Result<(), AppError> {
+        self.memory.find_contest(contest.0.id)?;
+        self.commit(Record::UpdateContest(save_contest(&contest)))
+    }
+}
+
+// some simple tests
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::job::JobInfo;
+
+    #[test]
+    fn test_memory_storage() {
+        let mut storage = MemoryStorage::default();
+        assert_eq!(storage.user_list().len(), 1);
+        assert_eq!(storage.next_user_id(), 1);
+        let info = JobInfo {
+            source_code: String::new(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        let id = storage.next_job_id();
+        storage.insert_job(Job::new("root", id, &info)).unwrap();
+        assert_eq!(storage.next_job_id(), 1);
+        assert_eq!(storage.find_job(0).unwrap().user_name, "root");
+        storage.remove_job(0).unwrap();
+        assert!(storage.find_job(0).is_err());
+        // the id of a removed job is not reused
+        assert_eq!(storage.next_job_id(), 1);
+    }
+    #[test]
+    fn test_file_storage() {
+        let data_file = "./tmp/test_file_storage/oj.json";
+        FileStorage::flush(data_file).unwrap();
+        let mut storage = FileStorage::open(data_file).unwrap();
+        storage.insert_user(User{ id: 1, name: "user1".to_string() }).unwrap();
+
+        let mut storage = FileStorage::open(data_file).unwrap();
+        assert_eq!(storage.next_user_id(), 2);
+        assert_eq!(storage.find_user(1).unwrap().name, "user1");
+        // the changes after the snapshot are replayed from the journal
+        storage.insert_user(User{ id: 2, name: "user2".to_string() }).unwrap();
+        storage.update_user(User{ id: 1, name: "user3".to_string() }).unwrap();
+        assert!(storage.update_user(User{ id: 5, name: "user5".to_string() }).is_err());
+        drop(storage);
+        // a record torn by a crash is left out
+        let journal = journal_file(data_file);
+        let mut file = OpenOptions::new().append(true).open(&journal).unwrap();
+        file.write_all(b"[4,{\"InsertUser\":{\"id\":3,").unwrap();
+        // the records already in the snapshot are not applied again
+        let copy = fs::read(&journal).unwrap();
+        let storage = FileStorage::open(data_file).unwrap();
+        assert_eq!(storage.next_user_id(), 3);
+        assert_eq!(storage.find_user(1).unwrap().name, "user3");
+        assert_eq!(fs::metadata(&journal).unwrap().len(), 0);
+        drop(storage);
+        fs::write(&journal, copy).unwrap();
+        let storage = FileStorage::open(data_file).unwrap();
+        assert_eq!(storage.user_list().len(), 3);
+        fs::remove_dir_all("./tmp/test_file_storage").unwrap();
+    }
+    #[test]
+    fn test_archive_storage() {
+        let data_file = "./tmp/test_archive_storage/oj.json";
+        FileStorage::flush(data_file).unwrap();
+        let mut storage = FileStorage::open(data_file).unwrap();
+        let info = JobInfo {
+            source_code: String::new(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: Some("YXJjaGl2ZQ==".to_string())
+        };
+        storage.submit_job(Job::new("root", 0, &info), None).unwrap();
+        let job = storage.find_job(0).unwrap().clone();
+        storage.update_job(job.clone()).unwrap();
+        // the archive is neither in the response nor in the journal
+        assert!(!serde_json::to_string(&job).unwrap().contains("YXJjaGl2ZQ=="));
+        assert!(!fs::read_to_string(journal_file(data_file)).unwrap().contains("YXJjaGl2ZQ=="));
+
+        let mut storage = FileStorage::open(data_file).unwrap();
+        assert!(!fs::read_to_string(data_file).unwrap().contains("YXJjaGl2ZQ=="));
+        assert_eq!(storage.find_job(0).unwrap().info.archive, info.archive);
+        storage.remove_job(0).unwrap();
+        assert!(!Path::new(&archive_file(data_file, 0)).exists());
+        fs::remove_dir_all("./tmp/test_archive_storage").unwrap();
+    }
+    #[test]
+    fn test_failed_write() {
+        let data_file = "./tmp/test_failed_write/oj.json";
+        FileStorage::flush(data_file).unwrap();
+        let mut storage = FileStorage::open(data_file).unwrap();
+        // the journal can not be written
+        storage.journal = File::open(journal_file(data_file)).unwrap();
+        assert!(storage.insert_user(User{ id: 1, name: "user1".to_string() }).is_err());
+        assert_eq!(storage.user_list().len(), 1);
+        assert_eq!(storage.next_user_id(), 1);
+        let info = JobInfo {
+            source_code: String::new(),
+            language: "Rust".to_string(),
+            user_id: 0,
+            contest_id: 0,
+            problem_id: 0,
+            archive: None
+        };
+        assert!(storage.submit_job(Job::new("root", 0, &info), None).is_err());
+        assert!(storage.job_list().is_empty());
+
+        // a job with a missing contest is not inserted
+        let mut storage = MemoryStorage::default();
+        let contest = ContestInfo {
+            id: 1,
+            name: "contest".to_string(),
+            from: String::new(),
+            to: String::new(),
+            problem_ids: Vec::new(),
+            user_ids: Vec::new(),
+            submission_limit: 1
+        };
+        let contest = (contest, HashMap::new());
+        assert!(storage.submit_job(Job::new("root", 0, &info), Some(contest)).is_err());
+        assert!(storage.job_list().is_empty());
+        fs::remove_dir_all("./tmp/test_failed_write").unwrap();
+    }
+}
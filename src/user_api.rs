@@ -32,7 +32,7 @@ pub async fn post_users(info: web::Json<UserInfo>) -> Result<HttpResponse, AppEr
 pub async fn get_users() -> impl Responder {
     let job_data = JOBDATA.clone();
     let job_data_inner = job_data.lock().unwrap();
-    let mut temp_user_list: Vec<User> = job_data_inner.user_list.iter().map(|x| {x.clone()}).collect();
+    let mut temp_user_list: Vec<User> = job_data_inner.storage().user_list().into_iter().cloned().collect();
     temp_user_list.sort_by_key(|x| {x.id});
     log::info!(target: "get_users", "get user list");
     return HttpResponse::Ok().json(temp_user_list);
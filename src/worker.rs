@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+
+use crate::config::Config;
+use crate::job::{Job, RunOutput};
+use crate::{JOBDATA, State, RunResult};
+
+
+// the work of a judge worker
+// a stored job by its id, or a custom run with its input
+// whose output is sent back through the channel
+enum Task {
+    Judge(u32),
+    Run(Box<Job>, String, mpsc::Sender<RunOutput>)
+}
+
+// the queue of the tasks waiting to be done
+// the condvar is used to wake up an idle worker
+lazy_static!(
+    static ref JOBQUEUE: (Mutex<VecDeque<Task>>, Condvar) = (Mutex::new(VecDeque::new()), Condvar::new());
+);
+
+// push a job to the queue and wake up a worker
+pub fn push_job(job_id: u32) {
+    push_task(Task::Judge(job_id));
+}
+
+// push a custom run to the queue
+// the output is received from the returned channel when the run is done
+// so that custom runs share the workers with the judging
+pub fn push_run(job: Job, input: String) -> mpsc::Receiver<RunOutput> {
+    let (sender, receiver) = mpsc::channel();
+    push_task(Task::Run(Box::new(job), input, sender));
+    return receiver;
+}
+
+fn push_task(task: Task) {
+    let (queue, cvar) = &*JOBQUEUE;
+    queue.lock().unwrap().push_back(task);
+    cvar.notify_one();
+}
+
+// spawn the judge workers in background
+// the number of workers is set in the server section of the config
+pub fn start_workers(config: &Config) {
+    for i in 0..config.server.judge_workers.max(1) {
+        let config = config.clone();
+        thread::Builder::new()
+            .name(format!("judge_worker_{}", i))
+            .spawn(move || { work(config) })
+            .expect("Spawn judge worker failed");
+    }
+}
+
+// block until there is a task in the queue
+fn pop_task() -> Task {
+    let (queue, cvar) = &*JOBQUEUE;
+    let mut queue = queue.lock().unwrap();
+    loop {
+        if let Some(task) = queue.pop_front() {
+            return task;
+        }
+        queue = cvar.wait(queue).unwrap();
+    }
+}
+
+// the loop of one worker
+// take a job from the queue, run it without holding the global lock
+// and then write the result back to the job list
+fn work(config: Config) {
+    loop {
+        let job_id = match pop_task() {
+            Task::Judge(job_id) => job_id,
+            Task::Run(mut job, input, sender) => {
+                // a panic is reported as the closed channel
+                let res = panic::catch_unwind(AssertUnwindSafe(|| { job.run_custom(&config, &input) }));
+                match res {
+                    Ok(output) => { let _ = sender.send(output); },
+                    Err(_) => log::error!(target: "judge_worker", "custom run {} panicked", job.job_id),
+                }
+                continue;
+            },
+        };
+
+        // mark the job as running and take a copy of it
+        let mut job = {
+            let mut job_data = JOBDATA.lock().unwrap();
+            let mut job = match job_data.storage().find_job(job_id) {
+                Ok(job) => job.clone(),
+                // the job has been deleted
+                Err(_) => continue,
+            };
+            if job.state != State::Queueing {
+                continue;
+            }
+            job.state = State::Running;
+            job.result = RunResult::Running;
+            job.updated_time = Utc::now();
+            if job_data.storage_mut().update_job(job.clone()).is_err() {
+                continue;
+            }
+            job
+        };
+
+        // a panic in the judging must not take down the worker
+        let res = panic::catch_unwind(AssertUnwindSafe(|| { job.run(&config); }));
+        if res.is_err() {
+            log::error!(target: "judge_worker", "job {} panicked", job_id);
+            job.state = State::Finished;
+            job.result = RunResult::SystemError;
+            job.updated_time = Utc::now();
+        }
+        log::info!(target: "judge_worker", "job {} finished", job_id);
+
+        let mut job_data = JOBDATA.lock().unwrap();
+        if let Err(e) = job_data.storage_mut().update_job(job) {
+            log::error!(target: "judge_worker", "update job {} failed {}", job_id, e);
+        }
+    }
+}
+
+// put the jobs not finished before the last shutdown back to the queue
+pub fn requeue_unfinished(config: &Config) {
+    let mut job_data = JOBDATA.lock().unwrap();
+    let unfinished: Vec<Job> = job_data.storage().job_list().into_iter().filter(|job| {
+        job.state == State::Queueing || job.state == State::Running
+    }).cloned().collect();
+    for mut job in unfinished {
+        let job_id = job.job_id;
+        // the problem or the language may have been removed from the config
+        if !job.is_valid(config) {
+            log::error!(target: "requeue_unfinished", "job {} can not be judged with the config", job_id);
+            job.state = State::Finished;
+            job.result = RunResult::SystemError;
+            job.updated_time = Utc::now();
+            if let Err(e) = job_data.storage_mut().update_job(job) {
+                log::error!(target: "requeue_unfinished", "update job {} failed {}", job_id, e);
+            }
+            continue;
+        }
+        job.reset(config);
+        if job_data.storage_mut().update_job(job).is_ok() {
+            push_job(job_id);
+        }
+    }
+}
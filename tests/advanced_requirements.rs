@@ -1,3 +1,5 @@
+// the harness is provided by the course and not linted
+#[allow(clippy::all)]
 mod common;
 use common::TestCase;
 use std::collections::BTreeMap;
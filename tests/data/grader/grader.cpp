@@ -0,0 +1,11 @@
+// the grader of the aplusb problem
+// the submission implements long long solve(long long a, long long b)
+#include <iostream>
+#include "solve.h"
+
+int main() {
+    long long a, b;
+    std::cin >> a >> b;
+    std::cout << solve(a, b) << std::endl;
+    return 0;
+}
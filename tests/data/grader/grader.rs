@@ -0,0 +1,11 @@
+// the grader of the aplusb problem
+// the submission implements fn solve(a: i64, b: i64) -> i64
+include!("main.rs");
+
+fn main() {
+    let mut sum = Vec::new();
+    for line in std::io::stdin().lines() {
+        sum.push(line.unwrap().trim().parse::<i64>().unwrap());
+    }
+    println!("{}", solve(sum[0], sum[1]));
+}
@@ -0,0 +1 @@
+long long solve(long long a, long long b);